@@ -46,7 +46,7 @@ macro_rules! gen_bpf_object_iter {
             type Item = *mut $iter_ty;
 
             fn next(&mut self) -> Option<Self::Item> {
-                self.last = unsafe { $next_fn(self.last, self.obj) };
+                self.last = unsafe { $next_fn(self.obj, self.last) };
 
                 if self.last.is_null() {
                     None
@@ -58,11 +58,15 @@ macro_rules! gen_bpf_object_iter {
     };
 }
 
-gen_bpf_object_iter!(MapIter, libbpf_sys::bpf_map, libbpf_sys::bpf_map__next);
+gen_bpf_object_iter!(
+    MapIter,
+    libbpf_sys::bpf_map,
+    libbpf_sys::bpf_object__next_map
+);
 gen_bpf_object_iter!(
     ProgIter,
     libbpf_sys::bpf_program,
-    libbpf_sys::bpf_program__next
+    libbpf_sys::bpf_object__next_program
 );
 
 /// Run `rustfmt` over `s` and return result
@@ -153,8 +157,7 @@ fn get_prog_name(prog: *const libbpf_sys::bpf_program) -> Result<String> {
 }
 
 fn map_is_mmapable(map: *const libbpf_sys::bpf_map) -> bool {
-    let def = unsafe { libbpf_sys::bpf_map__def(map) };
-    (unsafe { (*def).map_flags } & libbpf_sys::BPF_F_MMAPABLE) > 0
+    (unsafe { libbpf_sys::bpf_map__map_flags(map) } & libbpf_sys::BPF_F_MMAPABLE) > 0
 }
 
 fn map_is_datasec(map: *const libbpf_sys::bpf_map) -> bool {
@@ -166,10 +169,9 @@ fn map_is_datasec(map: *const libbpf_sys::bpf_map) -> bool {
 
 fn map_is_readonly(map: *const libbpf_sys::bpf_map) -> bool {
     assert!(map_is_mmapable(map));
-    let def = unsafe { libbpf_sys::bpf_map__def(map) };
 
     // BPF_F_RDONLY_PROG means readonly from prog side
-    (unsafe { (*def).map_flags } & libbpf_sys::BPF_F_RDONLY_PROG) > 0
+    (unsafe { libbpf_sys::bpf_map__map_flags(map) } & libbpf_sys::BPF_F_RDONLY_PROG) > 0
 }
 
 fn gen_skel_c_skel_constructor(
@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::ptr;
+
+use nix::errno;
+use num_enum::TryFromPrimitive;
+use strum_macros::Display;
+
+use crate::*;
+
+/// Kind of a [`BtfType`]. Maps to the `BTF_KIND_*` constants in kernel uapi.
+#[non_exhaustive]
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, TryFromPrimitive, PartialEq, Eq, Display)]
+pub enum BtfKind {
+    Unknown = 0,
+    Int,
+    Ptr,
+    Array,
+    Struct,
+    Union,
+    Enum,
+    Fwd,
+    Typedef,
+    Volatile,
+    Const,
+    Restrict,
+    Func,
+    FuncProto,
+    Var,
+    Datasec,
+    Float,
+    DeclTag,
+    TypeTag,
+    Enum64,
+}
+
+/// A type described by [`Btf`], see [`Btf::type_by_id()`] and [`Btf::type_by_name()`].
+#[derive(Clone, Copy, Debug)]
+pub struct BtfType<'a> {
+    id: u32,
+    name: Option<&'a str>,
+    kind: BtfKind,
+    vlen: u16,
+}
+
+impl<'a> BtfType<'a> {
+    /// Id of this type, e.g. for [`Btf::type_size()`].
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Name of this type, `None` for anonymous types.
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    pub fn kind(&self) -> BtfKind {
+        self.kind
+    }
+
+    /// Number of members of a struct or union, of values of an enum or of parameters of a
+    /// function prototype. 0 for other kinds.
+    pub fn vlen(&self) -> u16 {
+        self.vlen
+    }
+}
+
+/// BPF Type Format information, describing the types used by BPF programs and maps.
+///
+/// BTF obtained with [`Object::btf()`] borrows the object, everything else is owned and freed
+/// when dropped.
+pub struct Btf<'a> {
+    ptr: *mut libbpf_sys::btf,
+    owned: bool,
+    _marker: PhantomData<&'a Object>,
+}
+
+impl<'a> Btf<'a> {
+    /// Borrows the BTF of a loaded object, see [`Object::btf()`].
+    pub(crate) fn from_object(obj: *mut libbpf_sys::bpf_object) -> Option<Self> {
+        let ptr = unsafe { libbpf_sys::bpf_object__btf(obj) };
+        if ptr.is_null() {
+            return None;
+        }
+
+        Some(Btf {
+            ptr,
+            owned: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Parses BTF from the file at `path`, either an ELF object file with a `.BTF` section,
+    /// e.g. a compiled BPF object, or raw BTF such as `/sys/kernel/btf/vmlinux`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Btf<'static>> {
+        let path = util::path_to_cstring(path)?;
+        let ptr = unsafe { libbpf_sys::btf__parse(path.as_ptr(), ptr::null_mut()) };
+        if ptr.is_null() {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(Btf {
+            ptr,
+            owned: true,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Type `type_id`, if it exists.
+    pub fn type_by_id(&self, type_id: u32) -> Option<BtfType<'_>> {
+        let ty = unsafe { libbpf_sys::btf__type_by_id(self.ptr, type_id) };
+        if ty.is_null() {
+            return None;
+        }
+
+        // See struct btf_type in kernel uapi for the layout of info
+        let info = unsafe { (*ty).info };
+        Some(BtfType {
+            id: type_id,
+            name: self.name_by_offset(unsafe { (*ty).name_off }),
+            kind: BtfKind::try_from((info >> 24) & 0x1f).unwrap_or(BtfKind::Unknown),
+            vlen: (info & 0xffff) as u16,
+        })
+    }
+
+    /// First type named `name`, if there is one.
+    pub fn type_by_name(&self, name: &str) -> Option<BtfType<'_>> {
+        let c_name = util::str_to_cstring(name).ok()?;
+        let id = unsafe { libbpf_sys::btf__find_by_name(self.ptr, c_name.as_ptr()) };
+        if id < 0 {
+            return None;
+        }
+
+        self.type_by_id(id as u32)
+    }
+
+    /// Name of type `type_id`, if the type exists and is named.
+    pub fn type_name(&self, type_id: u32) -> Option<&str> {
+        self.type_by_id(type_id)?.name()
+    }
+
+    /// Number of types, including the implicit `void` type with id 0. Valid ids are below this.
+    pub fn type_cnt(&self) -> u32 {
+        unsafe { libbpf_sys::btf__type_cnt(self.ptr) }
+    }
+
+    fn name_by_offset(&self, offset: u32) -> Option<&str> {
+        let name = unsafe { libbpf_sys::btf__name_by_offset(self.ptr, offset) };
+        if name.is_null() {
+            return None;
+        }
+
+        match unsafe { CStr::from_ptr(name) }.to_str() {
+            Ok("") | Err(_) => None,
+            Ok(name) => Some(name),
+        }
+    }
+
+    /// Size in bytes of type `type_id`, following typedefs and modifiers.
+    pub fn type_size(&self, type_id: u32) -> Result<usize> {
+        let size = unsafe { libbpf_sys::btf__resolve_size(self.ptr, type_id) };
+        if size < 0 {
+            return Err(Error::System(-size as i32));
+        }
+
+        Ok(size as usize)
+    }
+}
+
+impl Drop for Btf<'_> {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { libbpf_sys::btf__free(self.ptr) }
+        }
+    }
+}
+
+/// Loads the BTF object with id `id` from the kernel, e.g. the one referenced by
+/// [`query::MapInfo::btf_id`]. Key and value types of such a map can then be resolved with
+/// [`query::MapInfo::btf_key_type_id`] and [`query::MapInfo::btf_value_type_id`].
+pub fn load_btf_by_id(id: u32) -> Result<Btf<'static>> {
+    let ptr = unsafe { libbpf_sys::btf__load_from_kernel_by_id(id) };
+    if ptr.is_null() {
+        return Err(Error::System(errno::errno()));
+    }
+
+    Ok(Btf {
+        ptr,
+        owned: true,
+        _marker: PhantomData,
+    })
+}
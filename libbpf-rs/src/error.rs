@@ -1,16 +1,35 @@
 use std::result;
 
+use nix::errno::Errno;
 use thiserror::Error;
 
 /// Canonical error type for this crate.
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("System error, errno: {0}")]
+    #[error("System error, errno: {}", errno_to_string(*.0))]
     System(i32),
-    #[error("Input input: {0}")]
+    #[error("Invalid input: {0}")]
     InvalidInput(String),
     #[error("Internal error: {0}")]
     Internal(String),
+    /// A program failed to load. Holds the errno the load failed with and the verifier log of
+    /// that program, prefixed by its name.
+    #[error("Verifier error, errno: {}:\n{log}", errno_to_string(*.errno))]
+    Verifier { errno: i32, log: String },
+    /// The running kernel does not support an operation, e.g. a batch operation on a kernel
+    /// or map type without batch support. Holds the errno the kernel failed with.
+    #[error("Unsupported operation, errno: {}", errno_to_string(*.0))]
+    Unsupported(i32),
+}
+
+/// Formats `errno` with its symbolic name and description, e.g. `1 (EPERM: Operation not
+/// permitted)`. Values unknown to libc, like the kernel-internal `ENOTSUPP` (524), are
+/// printed as numbers only.
+fn errno_to_string(errno: i32) -> String {
+    match Errno::from_i32(errno) {
+        Errno::UnknownErrno => errno.to_string(),
+        e => format!("{} ({})", errno, e),
+    }
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use crate::*;
+
+/// Consumes events from either a `ringbuf` or a `perf event array` [`Map`] through the same
+/// callback.
+///
+/// This lets one codebase use ring buffers where the kernel supports them (Linux 5.8 and
+/// newer) and fall back to perf buffers on older kernels. Which one is used follows from the
+/// type of the map passed to [`EventBuffer::new()`], so the BPF side decides by creating the
+/// map as either type.
+pub enum EventBuffer {
+    RingBuf(RingBuffer),
+    PerfEvent(PerfBuffer),
+}
+
+impl EventBuffer {
+    /// Sets up an [`EventBuffer`] calling `callback` for each event sent through `map`, which
+    /// must be a [`MapType::RingBuf`] or [`MapType::PerfEventArray`] map.
+    ///
+    /// The callback provides a raw byte slice. You may find libraries such as
+    /// [`plain`](https://crates.io/crates/plain) helpful. Use [`RingBufferBuilder`] or
+    /// [`PerfBufferBuilder`] directly for features specific to one of them, like lost event
+    /// notifications of perf buffers.
+    pub fn new<F>(map: &dyn MapOps, mut callback: F) -> Result<Self>
+    where
+        F: FnMut(&[u8]) + 'static,
+    {
+        match map.map_type() {
+            MapType::RingBuf => {
+                let mut builder = RingBufferBuilder::new();
+                builder.add(map, move |data: &[u8]| {
+                    callback(data);
+                    0
+                })?;
+                Ok(EventBuffer::RingBuf(builder.build()?))
+            }
+            MapType::PerfEventArray => {
+                let perf = PerfBufferBuilder::new(map)
+                    .sample_cb(move |_cpu: i32, data: &[u8]| callback(data))
+                    .build()?;
+                Ok(EventBuffer::PerfEvent(perf))
+            }
+            ty => Err(Error::InvalidInput(format!(
+                "{} is a {} map, expected a ringbuf or perf event array",
+                map.name(),
+                ty
+            ))),
+        }
+    }
+
+    /// Poll for events, calling the callback for each one, until there are no more events to
+    /// consume or `timeout` is reached.
+    pub fn poll(&self, timeout: Duration) -> Result<()> {
+        match self {
+            EventBuffer::RingBuf(ringbuf) => ringbuf.poll(timeout),
+            EventBuffer::PerfEvent(perf) => perf.poll(timeout),
+        }
+    }
+}
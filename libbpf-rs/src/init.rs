@@ -0,0 +1,102 @@
+use std::os::raw::c_char;
+
+use nix::{errno, libc};
+
+use crate::*;
+
+/// Options for [`init_with()`].
+#[derive(Clone, Debug)]
+pub struct InitOpts {
+    /// Mode passed to `libbpf_set_strict_mode()`. libbpf 1.0 and newer are always strict and
+    /// ignore it.
+    pub strict_mode: libbpf_sys::libbpf_strict_mode,
+    /// Raise the soft and hard `RLIMIT_MEMLOCK` to at least this many bytes. Limits that are
+    /// already high enough are left alone, `None` skips this step.
+    pub memlock_limit: Option<u64>,
+    /// Print libbpf debug output to stdout, see [`ObjectBuilder::debug()`]. If `false`, libbpf's
+    /// default print callback is kept.
+    pub debug: bool,
+}
+
+impl Default for InitOpts {
+    fn default() -> Self {
+        InitOpts {
+            strict_mode: libbpf_sys::LIBBPF_STRICT_ALL,
+            memlock_limit: Some(libc::RLIM_INFINITY),
+            debug: false,
+        }
+    }
+}
+
+/// Same as [`init_with()`] with the default [`InitOpts`].
+pub fn init() -> Result<()> {
+    init_with(&InitOpts::default())
+}
+
+/// Sets up the global libbpf state in one call: strict mode, then the print callback, then the
+/// `RLIMIT_MEMLOCK` limit. Call it once at startup, before opening any object, to fail early
+/// instead of on the first load.
+///
+/// Stops at the first step that fails. Setting the strict mode fails with an
+/// [`Error::Internal`] naming the mode, raising the limit with an [`Error::System`] holding
+/// the errno of `getrlimit()` or `setrlimit()`.
+pub fn init_with(opts: &InitOpts) -> Result<()> {
+    let ret = unsafe { libbpf_sys::libbpf_set_strict_mode(opts.strict_mode) };
+    if ret < 0 {
+        return Err(Error::Internal(format!(
+            "failed to set libbpf strict mode {:#x}: {}",
+            opts.strict_mode,
+            errno::Errno::from_i32(-ret)
+        )));
+    }
+
+    set_print(opts.debug);
+
+    if let Some(limit) = opts.memlock_limit {
+        raise_memlock_rlimit(limit)?;
+    }
+
+    Ok(())
+}
+
+/// Installs the stdout print callback if `debug` is set, or resets libbpf to its default one.
+pub(crate) fn set_print(debug: bool) {
+    extern "C" fn cb(
+        _level: libbpf_sys::libbpf_print_level,
+        fmtstr: *const c_char,
+        va_list: *mut libbpf_sys::__va_list_tag,
+    ) -> i32 {
+        match unsafe { vsprintf::vsprintf(fmtstr, va_list) } {
+            Ok(s) => {
+                print!("{}", s);
+                0
+            }
+            Err(e) => {
+                eprintln!("Failed to parse libbpf output: {}", e);
+                1
+            }
+        }
+    }
+
+    if debug {
+        unsafe { libbpf_sys::libbpf_set_print(Some(cb)) };
+    } else {
+        unsafe { libbpf_sys::libbpf_set_print(None) };
+    }
+}
+
+fn raise_memlock_rlimit(limit: u64) -> Result<()> {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlimit) } != 0 {
+        return Err(Error::System(errno::errno()));
+    }
+
+    if rlimit.rlim_cur >= limit && rlimit.rlim_max >= limit {
+        return Ok(());
+    }
+
+    wrappers::setrlimit_memlock(rlimit.rlim_cur.max(limit), rlimit.rlim_max.max(limit))
+}
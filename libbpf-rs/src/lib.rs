@@ -66,28 +66,49 @@
 //!
 //! [See example here](https://github.com/libbpf/libbpf-rs/tree/master/examples/runqslower).
 
+mod btf;
 mod error;
+mod event_buffer;
+mod init;
 mod iter;
 mod link;
 mod map;
 mod object;
 mod perf_buffer;
+mod perf_event;
 mod program;
 pub mod query;
 mod ringbuf;
 /// Used for skeleton -- an end user may not consider this API stable
 #[doc(hidden)]
 pub mod skeleton;
+mod tc;
 mod util;
 mod wrappers;
 
 pub use libbpf_sys;
 
+pub use crate::btf::{load_btf_by_id, Btf, BtfKind, BtfType};
 pub use crate::error::{Error, Result};
+pub use crate::event_buffer::EventBuffer;
+pub use crate::init::{init, init_with, InitOpts};
 pub use crate::iter::Iter;
-pub use crate::link::Link;
-pub use crate::map::{Map, MapFlags, MapOps, MapType, OpenMap, PinnedMap};
-pub use crate::object::{Object, ObjectBuilder, OpenObject};
+pub use crate::link::{iter_pinned_links, unpin_link, Link};
+pub use crate::map::{
+    CgroupStorageKey, Map, MapCreateFlags, MapCreateOpts, MapDef, MapFlags, MapIter, MapOps,
+    MapSlice, MapTryKeyIter, MapType, MmapRegion, OpenMap, PinnedMap, TaskStorageKey, TypedMap,
+};
+pub use crate::object::{Object, ObjectBinding, ObjectBuilder, OpenObject};
 pub use crate::perf_buffer::{PerfBuffer, PerfBufferBuilder};
-pub use crate::program::{OpenProgram, Program, ProgramAttachType, ProgramType};
+pub use crate::perf_event::{PerfEvent, PerfEventBuilder};
+pub use crate::program::{
+    create_program_from_insns, BenchResult, CgroupAttachFlags, KprobeOpts, OpenProgram,
+    ProgRunOpts, ProgRunResult, Program, ProgramAttachType, ProgramType, SkBuffContext,
+    TaskIterTarget, UprobeOpts,
+};
 pub use crate::ringbuf::{RingBuffer, RingBufferBuilder};
+pub use crate::tc::{TcAttachPoint, TcHook};
+pub use crate::util::detected_kernel_version;
+pub use crate::wrappers::{
+    num_possible_cpus, probe_map_type, probe_program_type, set_memlock_rlimit,
+};
@@ -1,6 +1,10 @@
-use nix::errno;
-use std::path::Path;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::ptr;
 
+use crate::query::LinkInfo;
 use crate::*;
 
 /// Represents an attached [`Program`].
@@ -9,11 +13,12 @@ use crate::*;
 /// when this object is dropped if nothing else is holding a reference count.
 pub struct Link {
     ptr: *mut libbpf_sys::bpf_link,
+    info: Option<LinkInfo>,
 }
 
 impl Link {
     pub(crate) fn new(ptr: *mut libbpf_sys::bpf_link) -> Self {
-        Link { ptr }
+        Link { ptr, info: None }
     }
 
     /// Takes ownership from pointer.
@@ -25,11 +30,120 @@ impl Link {
         Self::new(ptr)
     }
 
-    /// Replace the underlying prog with `prog`.
+    /// Open a link previously pinned to bpffs at `path`, taking ownership of it.
+    ///
+    /// The link's [`LinkInfo`] is read right away and cached, so [`Link::info()`] can tell
+    /// what an adopted link is attached to without further syscalls.
+    ///
+    /// Returns [`Error::InvalidInput`] if `path` refers to a pinned object that is not a link.
+    pub fn open_pinned<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path_c = util::path_to_cstring(&path)?;
+        let path_ptr = path_c.as_ptr();
+
+        let ptr = unsafe { libbpf_sys::bpf_link__open(path_ptr) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            return Err(Error::System(err as i32));
+        }
+
+        // bpf_link__open() accepts any pinned object, so check what we actually got
+        let mut link = Link::new(ptr);
+        if !is_link_fd(link.get_fd())? {
+            return Err(Error::InvalidInput(format!(
+                "{} is not a pinned link",
+                path.as_ref().display()
+            )));
+        }
+
+        link.info = Some(link.query_info()?);
+        Ok(link)
+    }
+
+    /// Returns information about the link, like its type, attach point and the id of the
+    /// attached program.
+    ///
+    /// Links opened with [`Link::open_pinned()`] return the info cached when opening them,
+    /// until [`Link::update_program()`] is called. Other links are queried on every call.
+    pub fn info(&self) -> Result<LinkInfo> {
+        match &self.info {
+            Some(info) => Ok(info.clone()),
+            None => self.query_info(),
+        }
+    }
+
+    fn query_info(&self) -> Result<LinkInfo> {
+        let fd = self.get_fd();
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_link_info>(fd)?;
+        LinkInfo::from_uapi(fd, info)
+            .ok_or_else(|| Error::Internal(format!("failed to read info of link fd {}", fd)))
+    }
+
+    /// Replace the underlying prog with `prog`, see [`Link::update_program()`].
     pub fn update_prog(&mut self, prog: Program) -> Result<()> {
-        let ret = unsafe { libbpf_sys::bpf_link__update_program(self.ptr, prog.ptr) };
+        self.update_program(&prog)
+    }
+
+    /// Atomically replace the program attached through this link with `prog`, e.g. to swap
+    /// the program of an XDP link without a window where no program is attached.
+    ///
+    /// `prog` must be compatible with the hook of the link, for instance an iterator program
+    /// for the same target. Not all link types support this, unsupported ones fail with
+    /// `EOPNOTSUPP` or `EINVAL`.
+    pub fn update_program(&mut self, prog: &Program) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_link_update(self.get_fd(), prog.fd(), ptr::null()) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            Err(Error::System(-ret))
+        } else {
+            // The cached info still names the old program
+            self.info = None;
+            Ok(())
+        }
+    }
+
+    /// Replace the struct_ops map of this link, returned by [`Map::attach_struct_ops()`], with
+    /// `map`. The new implementation takes over without the struct_ops being unregistered in
+    /// between.
+    ///
+    /// `map` must be a struct_ops map from a `.struct_ops.link` section of the same struct_ops
+    /// type. Returns [`Error::InvalidInput`] if this is not a struct_ops link or `map` is not a
+    /// struct_ops map.
+    pub fn update_map(&mut self, map: &Map) -> Result<()> {
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_link_info>(self.get_fd())?;
+        if info.type_ != libbpf_sys::BPF_LINK_TYPE_STRUCT_OPS {
+            return Err(Error::InvalidInput(format!(
+                "link {} is not a struct_ops link",
+                info.id
+            )));
+        }
+        if map.map_type() != MapType::StructOps {
+            return Err(Error::InvalidInput(format!(
+                "{} is not a struct_ops map",
+                map.name()
+            )));
+        }
+
+        let ret = unsafe { libbpf_sys::bpf_link__update_map(self.ptr, map.libbpf_map()?) };
         if ret != 0 {
-            Err(Error::System(errno::errno()))
+            // Error code is returned negative, flip to positive to match errno
+            Err(Error::System(-ret))
+        } else {
+            self.info = None;
+            Ok(())
+        }
+    }
+
+    /// Detach the link from its BPF hook now, instead of when it is dropped or, if pinned,
+    /// when the pin is removed too.
+    ///
+    /// After a successful detach the link, and any pin of it, no longer run the program.
+    /// Not all link types support this (Linux 5.9 and newer), unsupported ones fail with
+    /// `EOPNOTSUPP`.
+    pub fn detach(self) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_link__detach(self.ptr) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            Err(Error::System(-ret))
         } else {
             Ok(())
         }
@@ -76,9 +190,14 @@ impl Link {
     }
 
     /// Returns the file descriptor of the link.
-    pub fn get_fd(&self) -> i32 {
+    pub fn fd(&self) -> i32 {
         unsafe { libbpf_sys::bpf_link__fd(self.ptr) }
     }
+
+    /// Returns the file descriptor of the link, same as [`Link::fd()`].
+    pub fn get_fd(&self) -> i32 {
+        self.fd()
+    }
 }
 
 impl Drop for Link {
@@ -86,3 +205,53 @@ impl Drop for Link {
         let _ = unsafe { libbpf_sys::bpf_link__destroy(self.ptr) };
     }
 }
+
+/// Returns whether `fd` refers to a BPF link, as opposed to e.g. a map or program.
+fn is_link_fd(fd: i32) -> Result<bool> {
+    let target = fs::read_link(format!("/proc/self/fd/{}", fd)).map_err(util::io_error_to_error)?;
+    Ok(target.as_os_str() == "anon_inode:bpf-link")
+}
+
+/// Remove the link pinned to bpffs at `path`.
+///
+/// The link is opened and unpinned, or if it cannot be opened (e.g. for lack of permissions
+/// to the link itself), `path` is just removed from bpffs. Either way only the reference held
+/// by the pin goes away: the link is detached once no process holds a file descriptor to it
+/// anymore. A link that is still in use, e.g. by the agent that created it, stays attached.
+///
+/// Returns [`Error::InvalidInput`] without removing anything if `path` refers to a pinned
+/// object that is not a link.
+pub fn unpin_link<P: AsRef<Path>>(path: P) -> Result<()> {
+    match Link::open_pinned(&path) {
+        Ok(mut link) => link.unpin(),
+        Err(e @ Error::InvalidInput(_)) => Err(e),
+        Err(_) => fs::remove_file(path).map_err(util::io_error_to_error),
+    }
+}
+
+/// Walk the bpffs directory `dir` and open every link pinned directly inside it. Entries that
+/// are not pinned links (e.g. pinned maps or programs, or subdirectories) are skipped.
+///
+/// This is how a restarted process re-adopts links it pinned earlier.
+pub fn iter_pinned_links<P: AsRef<Path>>(dir: P) -> impl Iterator<Item = Result<(PathBuf, Link)>> {
+    let entries: Box<dyn Iterator<Item = io::Result<fs::DirEntry>>> = match fs::read_dir(dir) {
+        Ok(entries) => Box::new(entries),
+        Err(e) => Box::new(iter::once(Err(e))),
+    };
+
+    entries.filter_map(move |entry| {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => return Some(Err(util::io_error_to_error(e))),
+        };
+        if path.is_dir() {
+            return None;
+        }
+
+        match Link::open_pinned(&path) {
+            Ok(link) => Some(Ok((path, link))),
+            Err(Error::InvalidInput(_)) => None,
+            Err(e) => Some(Err(e)),
+        }
+    })
+}
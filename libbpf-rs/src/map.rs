@@ -1,18 +1,26 @@
 use core::ffi::c_void;
+use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::path::Path;
+use std::ffi::{CStr, OsStr};
+use std::marker::PhantomData;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::ptr;
 
 use bitflags::bitflags;
-use nix::{errno, unistd};
+use nix::{errno, libc, unistd};
 use num_enum::TryFromPrimitive;
+use plain::Plain;
 use strum_macros::Display;
 
 use crate::*;
 
 /// Represents a parsed but not yet loaded BPF map.
 ///
-/// This object exposes operations that need to happen before the map is created.
+/// This object exposes operations that need to happen before the map is created. As the map
+/// does not exist yet, an `OpenMap` has no file descriptor. Maps are created when their
+/// [`OpenObject`] is loaded.
 ///
 /// Some methods require working with raw bytes. You may find libraries such as
 /// [`plain`](https://crates.io/crates/plain) helpful.
@@ -51,6 +59,8 @@ impl OpenMap {
         Ok(())
     }
 
+    /// Set the maximum number of entries of this map. Must be called before the object is
+    /// loaded. See also [`OpenMap::resize()`].
     pub fn set_max_entries(&mut self, count: u32) -> Result<()> {
         let ret = unsafe { libbpf_sys::bpf_map__set_max_entries(self.ptr, count) };
 
@@ -62,6 +72,65 @@ impl OpenMap {
         Ok(())
     }
 
+    /// Returns the maximum number of entries the map will be created with.
+    pub fn max_entries(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_map__max_entries(self.ptr) }
+    }
+
+    /// Resize this map to hold `count` entries, e.g. to size a ring buffer from a memory
+    /// budget only known at runtime. Must be called before the object is loaded, which
+    /// creates the map.
+    ///
+    /// Returns the size the map will actually have: libbpf rounds the size of
+    /// [`MapType::RingBuf`] maps up to a power-of-two multiple of the page size.
+    pub fn resize(&mut self, count: u32) -> Result<u32> {
+        self.set_max_entries(count)?;
+        Ok(self.max_entries())
+    }
+
+    /// Allocate the map's memory on NUMA node `node`. Must be called before the object is
+    /// loaded. [`MapCreateFlags::NUMA_NODE`] is added to the map's flags, without it the
+    /// kernel ignores the node.
+    pub fn set_numa_node(&mut self, node: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_numa_node(self.ptr, node) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            return Err(Error::System(-ret));
+        }
+
+        self.set_map_flags(self.map_flags() | MapCreateFlags::NUMA_NODE.bits())
+    }
+
+    /// Set the flags the map is created with, e.g. [`MapCreateFlags::NO_PREALLOC`] to allocate
+    /// the entries of a large hash map on demand instead of upfront. Must be called before the
+    /// object is loaded. This replaces the flags from the map definition, see
+    /// [`OpenMap::map_flags()`].
+    pub fn set_map_flags(&mut self, flags: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_map_flags(self.ptr, flags) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            return Err(Error::System(-ret));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the flags the map will be created with.
+    pub fn map_flags(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_map__map_flags(self.ptr) }
+    }
+
+    /// Returns the NUMA node set with [`OpenMap::set_numa_node()`], 0 if none was set.
+    pub fn numa_node(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_map__numa_node(self.ptr) }
+    }
+
+    /// Set the bpffs path this map is pinned at when the object is loaded.
+    ///
+    /// If a compatible map is already pinned at `path`, e.g. by an earlier run, it is reused
+    /// instead of creating a new map. Otherwise the new map is pinned there right after being
+    /// created, so other processes never see the path without a map behind it. Loading fails
+    /// if an incompatible map is pinned at `path`.
     pub fn set_pin_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let path_c = util::path_to_cstring(path)?;
 
@@ -73,10 +142,72 @@ impl OpenMap {
         Ok(())
     }
 
+    /// Returns the path set with [`OpenMap::set_pin_path()`], if any.
+    pub fn pin_path(&self) -> Option<PathBuf> {
+        let path = unsafe { libbpf_sys::bpf_map__pin_path(self.ptr) };
+        if path.is_null() {
+            return None;
+        }
+
+        let path = unsafe { CStr::from_ptr(path) };
+        Some(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+    }
+
+    /// Set whether libbpf should create this map when the object is loaded. Use this to skip
+    /// maps the running kernel does not support, e.g. if they are only used by programs that
+    /// are not loaded either.
+    ///
+    /// A map that is not created will not show up in the loaded [`Object`].
+    pub fn set_autocreate(&mut self, autocreate: bool) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_map__set_autocreate(self.ptr, autocreate) };
+        if ret != 0 {
+            return Err(Error::System(-ret));
+        }
+
+        Ok(())
+    }
+
+    pub fn autocreate(&self) -> bool {
+        unsafe { libbpf_sys::bpf_map__autocreate(self.ptr) }
+    }
+
     pub fn set_inner_map_fd(&mut self, inner: &Map) {
         unsafe { libbpf_sys::bpf_map__set_inner_map_fd(self.ptr, inner.fd()) };
     }
 
+    /// Use the already created map `inner` as prototype for the inner maps of this
+    /// [`MapType::ArrayOfMaps`] or [`MapType::HashOfMaps`] map.
+    ///
+    /// Unlike [`OpenMap::set_inner_map_fd()`], this fails with [`Error::InvalidInput`] if this
+    /// map is not a map-in-map or if `inner` has no file descriptor, instead of failing at
+    /// load time. The inner map must come from an [`Object`] that was loaded already, an
+    /// [`OpenMap`] of the same object cannot serve as prototype.
+    pub fn set_inner_map(&mut self, inner: &Map) -> Result<()> {
+        let map_type = MapType::try_from(unsafe { libbpf_sys::bpf_map__type(self.ptr) })
+            .unwrap_or(MapType::Unknown);
+        if !map_type.is_map_of_maps() {
+            return Err(Error::InvalidInput(format!(
+                "map {} is a {} map, expected a map-in-map",
+                self.name, map_type
+            )));
+        }
+
+        if inner.fd() < 0 {
+            return Err(Error::InvalidInput(format!(
+                "inner map {} has not been created",
+                inner.name()
+            )));
+        }
+
+        let ret = unsafe { libbpf_sys::bpf_map__set_inner_map_fd(self.ptr, inner.fd()) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            return Err(Error::System(-ret));
+        }
+
+        Ok(())
+    }
+
     /// Reuse an already-pinned map for `self`.
     pub fn reuse_pinned_map<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let fd = wrappers::bpf_obj_get(path.as_ref())?;
@@ -95,6 +226,190 @@ impl OpenMap {
     }
 }
 
+/// Definition of a map as declared in a BPF object file. See [`OpenObject::map_definitions`].
+#[derive(Clone, Debug)]
+pub struct MapDef {
+    pub name: String,
+    pub map_type: MapType,
+    pub key_size: u32,
+    pub value_size: u32,
+    pub max_entries: u32,
+    pub map_flags: u32,
+}
+
+impl MapDef {
+    pub(crate) fn from_ptr(ptr: *const libbpf_sys::bpf_map) -> Result<Self> {
+        let name = util::c_ptr_to_string(unsafe { libbpf_sys::bpf_map__name(ptr) })?;
+        let map_type = match MapType::try_from(unsafe { libbpf_sys::bpf_map__type(ptr) }) {
+            Ok(t) => t,
+            Err(_) => MapType::Unknown,
+        };
+
+        Ok(MapDef {
+            name,
+            map_type,
+            key_size: unsafe { libbpf_sys::bpf_map__key_size(ptr) },
+            value_size: unsafe { libbpf_sys::bpf_map__value_size(ptr) },
+            max_entries: unsafe { libbpf_sys::bpf_map__max_entries(ptr) },
+            map_flags: unsafe { libbpf_sys::bpf_map__map_flags(ptr) },
+        })
+    }
+}
+
+/// Key of a [`MapType::CgroupStorage`] or [`MapType::PercpuCgroupStorage`] map. Maps to
+/// `struct bpf_cgroup_storage_key` in kernel uapi.
+///
+/// Use [`CgroupStorageKey::to_bytes`] to build the `key` argument of [`MapOps::lookup`] and
+/// [`MapOps::update`]. Cgroup storage entries are created by the kernel when a program using
+/// the map is attached to a cgroup, so only existing entries can be updated.
+#[derive(Clone, Debug)]
+pub struct CgroupStorageKey {
+    /// Id of the cgroup, which is the inode number of its cgroup2 directory.
+    pub cgroup_inode_id: u64,
+    /// Attach type of the program the storage belongs to. Ignored by maps keyed by cgroup id
+    /// only.
+    pub attach_type: ProgramAttachType,
+}
+
+impl CgroupStorageKey {
+    pub fn new(cgroup_inode_id: u64, attach_type: ProgramAttachType) -> Self {
+        CgroupStorageKey {
+            cgroup_inode_id,
+            attach_type,
+        }
+    }
+
+    /// Builds the key for the cgroup2 directory at `path` (e.g. `/sys/fs/cgroup/foo`).
+    pub fn from_cgroup_path<P: AsRef<Path>>(
+        path: P,
+        attach_type: ProgramAttachType,
+    ) -> Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(path).map_err(util::io_error_to_error)?;
+        if !metadata.is_dir() {
+            return Err(Error::InvalidInput("Expecting a cgroup directory!".into()));
+        }
+
+        Ok(Self::new(metadata.ino(), attach_type))
+    }
+
+    /// Returns the raw key for `map`.
+    ///
+    /// Cgroup storage maps are either keyed by `struct bpf_cgroup_storage_key` or by the
+    /// cgroup id alone, depending on the key size they were created with. Fails with
+    /// [`Error::InvalidInput`] if `map` is not a cgroup storage map or uses neither layout.
+    pub fn to_bytes(&self, map: &dyn MapOps) -> Result<Vec<u8>> {
+        let map_type = map.map_type();
+        if !matches!(
+            map_type,
+            MapType::CgroupStorage | MapType::PercpuCgroupStorage
+        ) {
+            return Err(Error::InvalidInput(format!(
+                "map {} is a {} map, expected a cgroup storage map",
+                map.name(),
+                map_type
+            )));
+        }
+
+        let key_size = map.key_size() as usize;
+        if key_size == mem::size_of::<u64>() {
+            return Ok(self.cgroup_inode_id.to_ne_bytes().to_vec());
+        }
+
+        if key_size != mem::size_of::<libbpf_sys::bpf_cgroup_storage_key>() {
+            return Err(Error::InvalidInput(format!(
+                "map {} has key size {}, expected {} or {}",
+                map.name(),
+                key_size,
+                mem::size_of::<u64>(),
+                mem::size_of::<libbpf_sys::bpf_cgroup_storage_key>()
+            )));
+        }
+
+        let key = libbpf_sys::bpf_cgroup_storage_key {
+            cgroup_inode_id: self.cgroup_inode_id,
+            attach_type: self.attach_type.clone() as u32,
+            ..Default::default()
+        };
+        let key_ptr = &key as *const libbpf_sys::bpf_cgroup_storage_key as *const u8;
+        Ok(unsafe { std::slice::from_raw_parts(key_ptr, key_size) }.to_vec())
+    }
+}
+
+/// Key of a [`MapType::TaskStorage`] map, which is a pidfd referring to the task.
+///
+/// Use [`TaskStorageKey::to_bytes`] to build the `key` argument of [`MapOps::lookup`],
+/// [`MapOps::update`] and [`MapOps::delete`]. The pidfd is closed when the key is dropped.
+#[derive(Debug)]
+pub struct TaskStorageKey {
+    pid_fd: i32,
+}
+
+impl TaskStorageKey {
+    /// Opens a pidfd for the process `pid`. Needs Linux 5.3 or newer.
+    pub fn from_pid(pid: u32) -> Result<Self> {
+        let pid_fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if pid_fd < 0 {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(TaskStorageKey {
+            pid_fd: pid_fd as i32,
+        })
+    }
+
+    /// The pidfd used as key.
+    pub fn pid_fd(&self) -> i32 {
+        self.pid_fd
+    }
+
+    /// Returns the raw key for `map`. Fails with [`Error::InvalidInput`] if `map` is not a
+    /// task storage map.
+    pub fn to_bytes(&self, map: &dyn MapOps) -> Result<Vec<u8>> {
+        let map_type = map.map_type();
+        if map_type != MapType::TaskStorage {
+            return Err(Error::InvalidInput(format!(
+                "map {} is a {} map, expected a task storage map",
+                map.name(),
+                map_type
+            )));
+        }
+
+        if map.key_size() as usize != mem::size_of::<i32>() {
+            return Err(Error::InvalidInput(format!(
+                "map {} has key size {}, expected {}",
+                map.name(),
+                map.key_size(),
+                mem::size_of::<i32>()
+            )));
+        }
+
+        Ok(self.pid_fd.to_ne_bytes().to_vec())
+    }
+}
+
+impl Drop for TaskStorageKey {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.pid_fd);
+    }
+}
+
+/// Returns the number of possible CPUs and the size of each CPU's slot in the value buffer
+/// of the per-CPU map `map`.
+fn percpu_layout(map: &(impl MapOps + ?Sized)) -> Result<(usize, usize)> {
+    if !map.map_type().is_per_cpu() {
+        return Err(Error::InvalidInput(format!(
+            "{} is not a per-CPU map",
+            map.name()
+        )));
+    }
+
+    let ncpus = wrappers::num_possible_cpus()?;
+    // Per-cpu values are laid out 8 byte aligned, one per possible cpu
+    Ok((ncpus, (map.value_size() as usize + 7) & !7))
+}
+
 pub trait MapOps {
     /// File Descriptor
     fn fd(&self) -> i32;
@@ -111,9 +426,16 @@ pub trait MapOps {
     /// Value size in bytes
     fn value_size(&self) -> u32;
 
+    /// Flags the map was created with. Flags unknown to this crate are dropped.
+    fn create_flags(&self) -> MapCreateFlags;
+
+    /// Maximum number of entries the map holds
+    fn max_entries(&self) -> u32;
+
     /// Returns map value as `Vec` of `u8`.
     ///
-    /// `key` must have exactly [`Map::key_size()`] elements.
+    /// `key` must have exactly [`Map::key_size()`] elements. Per-CPU maps hold one value per
+    /// CPU and return [`Error::InvalidInput`], use [`MapOps::lookup_percpu()`] for them.
     fn lookup(&self, key: &[u8], flags: MapFlags) -> Result<Option<Vec<u8>>> {
         if key.len() != self.key_size() as usize {
             return Err(Error::InvalidInput(format!(
@@ -123,6 +445,13 @@ pub trait MapOps {
             )));
         };
 
+        if self.map_type().is_per_cpu() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map, use lookup_percpu()",
+                self.name()
+            )));
+        }
+
         let mut out: Vec<u8> = Vec::with_capacity(self.value_size() as usize);
 
         let ret = unsafe {
@@ -149,6 +478,49 @@ pub trait MapOps {
         }
     }
 
+    /// Returns the values of a per-CPU map for `key`, one `Vec` of [`Map::value_size()`] bytes
+    /// per possible CPU, indexed by CPU number.
+    ///
+    /// `key` must have exactly [`Map::key_size()`] elements. Only per-CPU maps, see
+    /// [`MapType::is_per_cpu()`], are supported.
+    fn lookup_percpu(&self, key: &[u8], flags: MapFlags) -> Result<Option<Vec<Vec<u8>>>> {
+        if key.len() != self.key_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "key_size {} != {}",
+                key.len(),
+                self.key_size()
+            )));
+        };
+
+        let (ncpus, stride) = percpu_layout(self)?;
+        let mut out = vec![0u8; ncpus * stride];
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_elem_flags(
+                self.fd(),
+                key.as_ptr() as *const c_void,
+                out.as_mut_ptr() as *mut c_void,
+                flags.bits,
+            )
+        };
+
+        if ret == 0 {
+            let value_size = self.value_size() as usize;
+            Ok(Some(
+                out.chunks_exact(stride)
+                    .map(|chunk| chunk[..value_size].to_vec())
+                    .collect(),
+            ))
+        } else {
+            let errno = errno::errno();
+            if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                Ok(None)
+            } else {
+                Err(Error::System(errno))
+            }
+        }
+    }
+
     /// Deletes an element from the map.
     ///
     /// `key` must have exactly [`Map::key_size()`] elements.
@@ -172,12 +544,60 @@ pub trait MapOps {
         }
     }
 
+    /// Deletes `count` elements from the map with a single `bpf_map_delete_batch` call and
+    /// returns the number of elements actually deleted.
+    ///
+    /// `keys` must hold exactly `count` keys of [`Map::key_size()`] bytes each, back to back.
+    /// `elem_flags` and `flags` are passed on to the kernel as is. If a key does not exist,
+    /// the kernel stops at that key and this fails with `ENOENT`, so keys before it are
+    /// deleted.
+    ///
+    /// Fails with [`Error::Unsupported`] if the kernel or the map type lacks batch support, or
+    /// if the kernel rejects `elem_flags` or `flags`.
+    fn delete_batch(&self, keys: &[u8], count: u32, elem_flags: u64, flags: u64) -> Result<u32> {
+        if keys.len() != count as usize * self.key_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "keys size {} != {} * {}",
+                keys.len(),
+                count,
+                self.key_size()
+            )));
+        };
+
+        let opts = libbpf_sys::bpf_map_batch_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_map_batch_opts>() as libbpf_sys::size_t,
+            elem_flags,
+            flags,
+        };
+        let mut count = count;
+        let ret = unsafe {
+            libbpf_sys::bpf_map_delete_batch(
+                self.fd(),
+                keys.as_ptr() as *const c_void,
+                &mut count,
+                &opts,
+            )
+        };
+
+        if ret == 0 {
+            Ok(count)
+        } else {
+            // Error code is returned negative, flip to positive to match errno
+            Err(batch_error(-ret))
+        }
+    }
+
     /// Same as [`Map::lookup()`] except this also deletes the key from the map.
     ///
-    /// Note that this operation is currently only implemented in the kernel for [`MapType::Queue`]
-    /// and [`MapType::Stack`].
+    /// Note that this operation is only implemented in the kernel for [`MapType::Queue`] and
+    /// [`MapType::Stack`], and since Linux 5.14 for [`MapType::Hash`] and [`MapType::LruHash`],
+    /// as well as their per-CPU variants through [`MapOps::lookup_and_delete_percpu()`]. Use
+    /// [`MapOps::supports_lookup_and_delete()`] to check the running kernel. Other maps fail
+    /// with [`Error::Unsupported`].
     ///
-    /// `key` must have exactly [`Map::key_size()`] elements.
+    /// `key` must have exactly [`Map::key_size()`] elements. Like with [`MapOps::lookup()`],
+    /// per-CPU maps return [`Error::InvalidInput`], as the kernel would write one value per CPU.
+    /// Use [`MapOps::lookup_and_delete_percpu()`] for them.
     fn lookup_and_delete(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         if key.len() != self.key_size() as usize {
             return Err(Error::InvalidInput(format!(
@@ -187,6 +607,13 @@ pub trait MapOps {
             )));
         };
 
+        if self.map_type().is_per_cpu() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map, values must be looked up per CPU",
+                self.name()
+            )));
+        }
+
         let mut out: Vec<u8> = Vec::with_capacity(self.value_size() as usize);
 
         let ret = unsafe {
@@ -204,10 +631,56 @@ pub trait MapOps {
             Ok(Some(out))
         } else {
             let errno = errno::errno();
-            if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
-                Ok(None)
-            } else {
-                Err(Error::System(errno))
+            match errno::Errno::from_i32(errno) {
+                errno::Errno::ENOENT => Ok(None),
+                // Maps without the operation fail with ENOTSUPP (524)
+                errno::Errno::EOPNOTSUPP => Err(Error::Unsupported(errno)),
+                _ if errno == 524 => Err(Error::Unsupported(errno)),
+                _ => Err(Error::System(errno)),
+            }
+        }
+    }
+
+    /// Same as [`MapOps::lookup_percpu()`] except this also deletes the key from the map.
+    ///
+    /// Only [`MapType::PercpuHash`] and [`MapType::LruPercpuHash`] implement this, since Linux
+    /// 5.14. Other per-CPU maps fail with [`Error::Unsupported`], maps that aren't per-CPU
+    /// with [`Error::InvalidInput`].
+    fn lookup_and_delete_percpu(&self, key: &[u8]) -> Result<Option<Vec<Vec<u8>>>> {
+        if key.len() != self.key_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "key_size {} != {}",
+                key.len(),
+                self.key_size()
+            )));
+        };
+
+        let (ncpus, stride) = percpu_layout(self)?;
+        let mut out = vec![0u8; ncpus * stride];
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_and_delete_elem(
+                self.fd(),
+                key.as_ptr() as *const c_void,
+                out.as_mut_ptr() as *mut c_void,
+            )
+        };
+
+        if ret == 0 {
+            let value_size = self.value_size() as usize;
+            Ok(Some(
+                out.chunks_exact(stride)
+                    .map(|chunk| chunk[..value_size].to_vec())
+                    .collect(),
+            ))
+        } else {
+            let errno = errno::errno();
+            match errno::Errno::from_i32(errno) {
+                errno::Errno::ENOENT => Ok(None),
+                // Maps without the operation fail with ENOTSUPP (524)
+                errno::Errno::EOPNOTSUPP => Err(Error::Unsupported(errno)),
+                _ if errno == 524 => Err(Error::Unsupported(errno)),
+                _ => Err(Error::System(errno)),
             }
         }
     }
@@ -215,7 +688,8 @@ pub trait MapOps {
     /// Update an element.
     ///
     /// `key` must have exactly [`Map::key_size()`] elements. `value` must have exatly
-    /// [`Map::value_size()`] elements.
+    /// [`Map::value_size()`] elements. Per-CPU maps return [`Error::InvalidInput`], use
+    /// [`MapOps::update_percpu()`] for them.
     fn update(&self, key: &[u8], value: &[u8], flags: MapFlags) -> Result<()> {
         if key.len() != self.key_size() as usize {
             return Err(Error::InvalidInput(format!(
@@ -225,6 +699,13 @@ pub trait MapOps {
             )));
         };
 
+        if self.map_type().is_per_cpu() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map, use update_percpu()",
+                self.name()
+            )));
+        }
+
         if value.len() != self.value_size() as usize {
             return Err(Error::InvalidInput(format!(
                 "value_size {} != {}",
@@ -249,16 +730,447 @@ pub trait MapOps {
         }
     }
 
+    /// Update the values of a per-CPU map for `key`, with one value of [`Map::value_size()`]
+    /// bytes per possible CPU in `values`, indexed by CPU number.
+    ///
+    /// `key` must have exactly [`Map::key_size()`] elements. Only per-CPU maps, see
+    /// [`MapType::is_per_cpu()`], are supported.
+    fn update_percpu(&self, key: &[u8], values: &[Vec<u8>], flags: MapFlags) -> Result<()> {
+        if key.len() != self.key_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "key_size {} != {}",
+                key.len(),
+                self.key_size()
+            )));
+        };
+
+        let (ncpus, stride) = percpu_layout(self)?;
+        if values.len() != ncpus {
+            return Err(Error::InvalidInput(format!(
+                "got {} values for {} possible CPUs",
+                values.len(),
+                ncpus
+            )));
+        }
+
+        let mut buf = vec![0u8; ncpus * stride];
+        for (chunk, value) in buf.chunks_exact_mut(stride).zip(values) {
+            if value.len() != self.value_size() as usize {
+                return Err(Error::InvalidInput(format!(
+                    "value_size {} != {}",
+                    value.len(),
+                    self.value_size()
+                )));
+            }
+            chunk[..value.len()].copy_from_slice(value);
+        }
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_elem(
+                self.fd(),
+                key.as_ptr() as *const c_void,
+                buf.as_ptr() as *const c_void,
+                flags.bits,
+            )
+        };
+
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(Error::System(errno::errno()))
+        }
+    }
+
+    /// Update an element and return the value it held before, or `None` if `key` was not
+    /// present.
+    ///
+    /// This is a [`Map::lookup()`] followed by a [`Map::update()`] and is **not** atomic: if
+    /// anything else (including BPF programs) writes to `key` in between, that write is silently
+    /// lost and the returned value is stale. Only use this when there is a single writer.
+    ///
+    /// `key` must have exactly [`Map::key_size()`] elements. `value` must have exatly
+    /// [`Map::value_size()`] elements.
+    fn swap(&self, key: &[u8], value: &[u8], flags: MapFlags) -> Result<Option<Vec<u8>>> {
+        if value.len() != self.value_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "value_size {} != {}",
+                value.len(),
+                self.value_size()
+            )));
+        };
+
+        // A missing key shows up as `None` here, not as an error. Whether that is acceptable
+        // is left to `update()` and `flags` (e.g. `MapFlags::EXIST` fails with ENOENT).
+        let prev = self.lookup(key, MapFlags::empty())?;
+        self.update(key, value, flags)?;
+
+        Ok(prev)
+    }
+
+    /// Same as [`MapOps::lookup()`], but reads the value while holding the `struct
+    /// bpf_spin_lock` embedded in it, so the value is not torn by concurrent updates.
+    ///
+    /// The map value type, as described by the map's BTF, must contain exactly one `struct
+    /// bpf_spin_lock` field, otherwise this fails with `EINVAL`. The bytes of the lock field
+    /// are returned zeroed.
+    fn lookup_locked(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.lookup(key, MapFlags::LOCK)
+    }
+
+    /// Same as [`MapOps::update()`], but writes the value while holding the `struct
+    /// bpf_spin_lock` embedded in it. The lock field of `value` is ignored.
+    ///
+    /// The same requirements as for [`MapOps::lookup_locked()`] apply.
+    fn update_locked(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.update(key, value, MapFlags::LOCK)
+    }
+
+    /// Store `inner` at `key` of this [`MapType::ArrayOfMaps`] or [`MapType::HashOfMaps`] map.
+    ///
+    /// This writes the fd of `inner` as the `u32` value the kernel expects. `inner` must match
+    /// the inner map template the outer map was created with. Note that looking up `key`
+    /// afterwards returns the id of the inner map, not its fd.
+    fn update_inner_map(&self, key: &[u8], inner: &dyn MapOps, flags: MapFlags) -> Result<()> {
+        let ty = self.map_type();
+        if !ty.is_map_of_maps() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a {} map, not a map of maps",
+                self.name(),
+                ty.kernel_name()
+            )));
+        }
+
+        self.update(key, &(inner.fd() as u32).to_ne_bytes(), flags)
+    }
+
+    /// Push `value` onto this [`MapType::Queue`] or [`MapType::Stack`] map.
+    ///
+    /// `value` must have exactly [`Map::value_size()`] elements. When the map is full this
+    /// fails with `E2BIG`, unless `flags` contains [`MapFlags::EXIST`], in which case the
+    /// oldest element is dropped to make room.
+    fn push(&self, value: &[u8], flags: MapFlags) -> Result<()> {
+        check_keyless(self, &[MapType::Queue, MapType::Stack], value)?;
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_elem(
+                self.fd(),
+                ptr::null(),
+                value.as_ptr() as *const c_void,
+                flags.bits,
+            )
+        };
+
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(Error::System(errno::errno()))
+        }
+    }
+
+    /// Remove and return the next element of this [`MapType::Queue`] or [`MapType::Stack`]
+    /// map, i.e. the oldest one of a queue or the newest one of a stack. Returns `None` if
+    /// the map is empty.
+    fn pop(&self) -> Result<Option<Vec<u8>>> {
+        let mut out = vec![0; self.value_size() as usize];
+        check_keyless(self, &[MapType::Queue, MapType::Stack], &out)?;
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_and_delete_elem(
+                self.fd(),
+                ptr::null(),
+                out.as_mut_ptr() as *mut c_void,
+            )
+        };
+
+        if ret == 0 {
+            Ok(Some(out))
+        } else {
+            let errno = errno::errno();
+            if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                Ok(None)
+            } else {
+                Err(Error::System(errno))
+            }
+        }
+    }
+
+    /// Add `value` to this [`MapType::BloomFilter`] map.
+    ///
+    /// `value` must have exactly [`Map::value_size()`] elements.
+    fn bloom_add(&self, value: &[u8]) -> Result<()> {
+        check_keyless(self, &[MapType::BloomFilter], value)?;
+
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_elem(
+                self.fd(),
+                ptr::null(),
+                value.as_ptr() as *const c_void,
+                libbpf_sys::BPF_ANY as u64,
+            )
+        };
+
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(Error::System(errno::errno()))
+        }
+    }
+
+    /// Check whether `value` may have been added to this [`MapType::BloomFilter`] map. Like
+    /// all Bloom filters this can return false positives, but never false negatives.
+    ///
+    /// `value` must have exactly [`Map::value_size()`] elements.
+    fn bloom_contains(&self, value: &[u8]) -> Result<bool> {
+        check_keyless(self, &[MapType::BloomFilter], value)?;
+
+        // The kernel only reads `value`, it is not written back for Bloom filters
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_elem(self.fd(), ptr::null(), value.as_ptr() as *mut c_void)
+        };
+
+        if ret == 0 {
+            Ok(true)
+        } else {
+            let errno = errno::errno();
+            if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                Ok(false)
+            } else {
+                Err(Error::System(errno))
+            }
+        }
+    }
+
+    /// Collect all entries of this map into a [`HashMap`].
+    ///
+    /// This materializes the whole map in memory, so it is only appropriate for small maps.
+    /// Keys deleted while the map is being read are skipped. Per-CPU maps are not supported
+    /// and return [`Error::InvalidInput`].
+    fn to_hashmap(&self) -> Result<HashMap<Vec<u8>, Vec<u8>>> {
+        if self.map_type().is_per_cpu() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map, values must be looked up per CPU",
+                self.name()
+            )));
+        }
+
+        let mut entries = HashMap::new();
+        for key in self.try_keys() {
+            let key = key?;
+            if let Some(value) = self.lookup(&key, MapFlags::empty())? {
+                entries.insert(key, value);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Count the entries of this map by walking its keys with `bpf_map_get_next_key`.
+    ///
+    /// This takes one syscall per entry. Like [`MapOps::keys()`], the count is only a snapshot
+    /// if the map is modified concurrently, and may then count some keys twice or miss some.
+    /// Array maps always contain `max_entries` entries. [`Map::approximate_count()`] needs far
+    /// fewer syscalls for large maps.
+    fn count_entries(&self) -> Result<usize> {
+        let mut prev: Option<Vec<u8>> = None;
+        let mut next = vec![0u8; self.key_size() as usize];
+        let mut count = 0;
+        loop {
+            let prev_ptr = prev.as_ref().map_or(ptr::null(), |p| p.as_ptr());
+            let ret = unsafe {
+                libbpf_sys::bpf_map_get_next_key(
+                    self.fd(),
+                    prev_ptr as *const c_void,
+                    next.as_mut_ptr() as *mut c_void,
+                )
+            };
+            if ret != 0 {
+                let errno = errno::errno();
+                if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                    return Ok(count);
+                }
+                return Err(Error::System(errno));
+            }
+
+            count += 1;
+            prev = Some(next.clone());
+        }
+    }
+
+    /// Returns the memory in bytes the kernel charges for this map, as shown by
+    /// `bpftool map show`. Read from the `memlock` field of the map's fdinfo.
+    fn memlock_bytes(&self) -> Result<u64> {
+        util::fdinfo_field(self.fd(), "memlock")
+    }
+
+    /// Probes whether the running kernel implements [`MapOps::lookup_and_delete()`] for maps
+    /// like this one.
+    ///
+    /// Map types the kernel never implements the operation for return `false` right away.
+    /// For the others, the probe runs on a throwaway map with the same type, key and value
+    /// size, so the contents of this map are never touched. The throwaway map is closed before
+    /// returning.
+    fn supports_lookup_and_delete(&self) -> Result<bool> {
+        let value_size = match self.map_type() {
+            MapType::Unknown => {
+                return Err(Error::InvalidInput(format!(
+                    "map {} has an unknown type",
+                    self.name()
+                )))
+            }
+            MapType::PercpuHash | MapType::LruPercpuHash => {
+                let ncpus = wrappers::num_possible_cpus()?;
+                ((self.value_size() as usize + 7) & !7) * ncpus
+            }
+            MapType::Hash | MapType::LruHash | MapType::Queue | MapType::Stack => {
+                self.value_size() as usize
+            }
+            // Also covers maps a plain throwaway map can't be created for, like maps of maps,
+            // ring buffers or storage maps
+            _ => return Ok(false),
+        };
+
+        let fd = unsafe {
+            libbpf_sys::bpf_map_create(
+                self.map_type() as u32,
+                ptr::null(),
+                self.key_size(),
+                self.value_size(),
+                1,
+                ptr::null(),
+            )
+        };
+        if fd < 0 {
+            return Err(Error::System(-fd));
+        }
+
+        let key = vec![0u8; self.key_size() as usize];
+        let mut value = vec![0u8; value_size];
+        let ret = unsafe {
+            libbpf_sys::bpf_map_lookup_and_delete_elem(
+                fd,
+                key.as_ptr() as *const c_void,
+                value.as_mut_ptr() as *mut c_void,
+            )
+        };
+        let _ = unistd::close(fd);
+
+        // The map is empty, so a supported lookup fails with ENOENT. Unsupported map types
+        // fail with ENOTSUPP (524), kernels predating the command with EINVAL.
+        match -ret {
+            0 => Ok(true),
+            e if e == errno::Errno::ENOENT as i32 => Ok(true),
+            e if e == 524
+                || e == errno::Errno::EOPNOTSUPP as i32
+                || e == errno::Errno::EINVAL as i32 =>
+            {
+                Ok(false)
+            }
+            e => Err(Error::System(e)),
+        }
+    }
+
     /// Returns an iterator over keys in this map
     ///
     /// Note that if the map is not stable (stable meaning no updates or deletes) during iteration,
     /// iteration can skip keys, restart from the beginning, or duplicate keys. In other words,
     /// iteration becomes unpredictable.
+    ///
+    /// Iteration also ends if reading the next key fails, use [`MapOps::try_keys()`] to tell
+    /// errors apart from the end of the map.
     fn keys(&self) -> MapKeyIter;
+
+    /// Returns an iterator over keys in this map that yields an error if reading the next key
+    /// fails, and ends only once there are no keys left. Iteration stops after the first
+    /// error.
+    ///
+    /// The same caveats as for [`MapOps::keys()`] apply if the map is not stable during
+    /// iteration.
+    fn try_keys(&self) -> MapTryKeyIter<'_> {
+        MapTryKeyIter {
+            keys: self.keys(),
+            done: false,
+        }
+    }
+
+    /// Returns an iterator over `(key, value)` pairs in this map, looking up each key as it
+    /// is returned. Keys deleted before their value could be read are skipped.
+    ///
+    /// The same caveats as for [`MapOps::keys()`] apply if the map is not stable during
+    /// iteration. Like [`MapOps::try_keys()`], errors reading a key or value are yielded and
+    /// end the iteration. Per-CPU maps yield a single [`Error::InvalidInput`], as their values
+    /// can only be read with [`MapOps::lookup_percpu()`].
+    fn iter(&self) -> MapIter<'_> {
+        MapIter {
+            keys: self.try_keys(),
+        }
+    }
+}
+
+/// Turns the errno of a failed batch operation into an error. Kernels predating the batch
+/// commands fail with EINVAL, maps without batch ops with ENOTSUPP (524) or EOPNOTSUPP.
+fn batch_error(errno: i32) -> Error {
+    if errno == errno::Errno::EINVAL as i32
+        || errno == errno::Errno::EOPNOTSUPP as i32
+        || errno == 524
+    {
+        Error::Unsupported(errno)
+    } else {
+        Error::System(errno)
+    }
+}
+
+/// Checks that `map` is one of the keyless map types in `types` and that `value` fits it.
+fn check_keyless<M: MapOps + ?Sized>(map: &M, types: &[MapType], value: &[u8]) -> Result<()> {
+    let ty = map.map_type();
+    if !types.contains(&ty) {
+        return Err(Error::InvalidInput(format!(
+            "{} is a {} map, not a {} map",
+            map.name(),
+            ty.kernel_name(),
+            types
+                .iter()
+                .map(|ty| ty.kernel_name())
+                .collect::<Vec<_>>()
+                .join(" or ")
+        )));
+    }
+
+    if value.len() != map.value_size() as usize {
+        return Err(Error::InvalidInput(format!(
+            "value_size {} != {}",
+            value.len(),
+            map.value_size()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Options for [`Map::create`].
+#[derive(Clone, Debug, Default)]
+pub struct MapCreateOpts {
+    /// File descriptor of a BTF object describing the key and value types of the map. The
+    /// type ids below are only used if this is set.
+    pub btf_fd: Option<i32>,
+    /// Id of the key type in the BTF object.
+    pub btf_key_type_id: u32,
+    /// Id of the value type in the BTF object.
+    pub btf_value_type_id: u32,
+    /// Flags to create the map with. [`MapCreateFlags::NUMA_NODE`] is added if `numa_node`
+    /// is set.
+    pub map_flags: MapCreateFlags,
+    /// NUMA node to allocate the map's memory on.
+    pub numa_node: Option<u32>,
+    /// File descriptor of a map serving as template for the inner maps, required for
+    /// [`MapType::ArrayOfMaps`] and [`MapType::HashOfMaps`] maps.
+    pub inner_map_fd: Option<i32>,
 }
 
 /// Represents a created map.
 ///
+/// A `Map` created with [`Map::create`] does not belong to an [`Object`] and only owns its
+/// file descriptor.
+///
 /// Some methods require working with raw bytes. You may find libraries such as
 /// [`plain`](https://crates.io/crates/plain) helpful.
 pub struct Map {
@@ -267,26 +1179,167 @@ pub struct Map {
     ty: libbpf_sys::bpf_map_type,
     key_size: u32,
     value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
     ptr: *mut libbpf_sys::bpf_map,
 }
 
 impl Map {
+    /// Takes the type, key and value sizes, `max_entries` and flags of the map from `info`,
+    /// as they can't change once the map is created.
     pub(crate) fn new(
         fd: i32,
         name: String,
-        ty: libbpf_sys::bpf_map_type,
-        key_size: u32,
-        value_size: u32,
+        info: &libbpf_sys::bpf_map_info,
         ptr: *mut libbpf_sys::bpf_map,
     ) -> Self {
         Map {
             fd,
             name,
-            ty,
+            ty: info.type_,
+            key_size: info.key_size,
+            value_size: info.value_size,
+            max_entries: info.max_entries,
+            map_flags: info.map_flags,
+            ptr,
+        }
+    }
+
+    /// Returns the libbpf map backing this `Map`, if it was loaded from an [`Object`].
+    pub(crate) fn libbpf_map(&self) -> Result<*mut libbpf_sys::bpf_map> {
+        if self.ptr.is_null() {
+            Err(Error::InvalidInput(
+                "map was not loaded from an Object".to_string(),
+            ))
+        } else {
+            Ok(self.ptr)
+        }
+    }
+
+    /// Create a new map of type `map_type` named `name` with `create_opts`, without going
+    /// through an [`Object`].
+    ///
+    /// If [`MapCreateOpts::btf_fd`] is set, the key and value type ids must exist in that BTF
+    /// object and match `key_size` and `value_size`, otherwise this fails with
+    /// [`Error::InvalidInput`].
+    pub fn create(
+        map_type: MapType,
+        name: &str,
+        key_size: u32,
+        value_size: u32,
+        max_entries: u32,
+        create_opts: MapCreateOpts,
+    ) -> Result<Map> {
+        let name_c = util::str_to_cstring(name)?;
+        let mut opts = libbpf_sys::bpf_map_create_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+            ..Default::default()
+        };
+
+        if let Some(btf_fd) = create_opts.btf_fd {
+            let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_btf_info>(btf_fd)?;
+            let btf = load_btf_by_id(info.id)?;
+            for (what, type_id, size) in &[
+                ("key", create_opts.btf_key_type_id, key_size),
+                ("value", create_opts.btf_value_type_id, value_size),
+            ] {
+                match btf.type_size(*type_id) {
+                    Ok(type_size) if type_size == *size as usize => (),
+                    Ok(type_size) => {
+                        return Err(Error::InvalidInput(format!(
+                            "{} type {} has size {}, expected {}",
+                            what, type_id, type_size, size
+                        )))
+                    }
+                    Err(_) => {
+                        return Err(Error::InvalidInput(format!(
+                            "{} type {} not found in BTF object {}",
+                            what, type_id, info.id
+                        )))
+                    }
+                }
+            }
+
+            opts.btf_fd = btf_fd as u32;
+            opts.btf_key_type_id = create_opts.btf_key_type_id;
+            opts.btf_value_type_id = create_opts.btf_value_type_id;
+        }
+
+        let mut map_flags = create_opts.map_flags;
+        if let Some(numa_node) = create_opts.numa_node {
+            map_flags |= MapCreateFlags::NUMA_NODE;
+            opts.numa_node = numa_node;
+        }
+        opts.map_flags = map_flags.bits();
+
+        if let Some(inner_map_fd) = create_opts.inner_map_fd {
+            opts.inner_map_fd = inner_map_fd as u32;
+        }
+
+        let ty = map_type as libbpf_sys::bpf_map_type;
+        let fd = unsafe {
+            libbpf_sys::bpf_map_create(
+                ty,
+                name_c.as_ptr(),
+                key_size,
+                value_size,
+                max_entries,
+                &opts,
+            )
+        };
+        if fd < 0 {
+            // Error code is returned negative, flip to positive to match errno
+            return Err(Error::System(-fd));
+        }
+
+        let info = libbpf_sys::bpf_map_info {
+            type_: ty,
             key_size,
             value_size,
-            ptr,
+            max_entries,
+            map_flags: opts.map_flags,
+            ..Default::default()
+        };
+        Ok(Map::new(fd, name.to_string(), &info, ptr::null_mut()))
+    }
+
+    /// Open the existing map with id `id`, e.g. one created by another process and found
+    /// with [`query::MapInfoIter`]. No bpffs pin is needed, but opening maps by id requires
+    /// `CAP_SYS_ADMIN`.
+    ///
+    /// Like maps from [`Map::create()`] the returned map does not belong to an [`Object`] and
+    /// owns its file descriptor. The map stays alive as long as it is open, even if its
+    /// creator goes away.
+    pub fn from_id(id: u32) -> Result<Map> {
+        let fd = unsafe { libbpf_sys::bpf_map_get_fd_by_id(id) };
+        if fd < 0 {
+            return Err(Error::System(errno::errno()));
         }
+
+        let info = match wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_map_info>(fd) {
+            Ok(info) => info,
+            Err(e) => {
+                let _ = unistd::close(fd);
+                return Err(e);
+            }
+        };
+
+        Ok(Map::new(
+            fd,
+            query::name_arr_to_string(&info.name, ""),
+            &info,
+            ptr::null_mut(),
+        ))
+    }
+
+    fn raw_info(&self) -> Result<libbpf_sys::bpf_map_info> {
+        wrappers::bpf_obj_get_info_by_fd(self.fd)
+    }
+
+    /// Returns what the kernel reports about this map, such as its id and name.
+    pub fn info(&self) -> Result<query::MapInfo> {
+        query::MapInfo::from_uapi(self.fd, self.raw_info()?)
+            .ok_or_else(|| Error::Internal("failed to parse map info".to_string()))
     }
 
     /// [Pin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
@@ -295,7 +1348,11 @@ impl Map {
         let path_c = util::path_to_cstring(path)?;
         let path_ptr = path_c.as_ptr();
 
-        let ret = unsafe { libbpf_sys::bpf_map__pin(self.ptr, path_ptr) };
+        let ret = if self.ptr.is_null() {
+            unsafe { libbpf_sys::bpf_obj_pin(self.fd, path_ptr) }
+        } else {
+            unsafe { libbpf_sys::bpf_map__pin(self.ptr, path_ptr) }
+        };
         if ret != 0 {
             // Error code is returned negative, flip to positive to match errno
             Err(Error::System(-ret))
@@ -304,9 +1361,51 @@ impl Map {
         }
     }
 
+    /// Same as [`Map::pin()`], but also sets the permissions of the pinned file to `mode` (e.g.
+    /// `0o640`). If that fails, the map is unpinned again.
+    ///
+    /// Note that other users also need search permission on every parent directory of `path`
+    /// to access the pinned map.
+    pub fn pin_with_mode<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> Result<()> {
+        self.pin(&path)?;
+        if let Err(e) = util::set_mode(&path, mode) {
+            let _ = self.unpin(&path);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether libbpf pinned this map, either with [`Map::pin()`] or at load time
+    /// because of [`OpenMap::set_pin_path()`]. Always `false` for maps that were not loaded
+    /// from an [`Object`].
+    pub fn is_pinned(&self) -> bool {
+        !self.ptr.is_null() && unsafe { libbpf_sys::bpf_map__is_pinned(self.ptr) }
+    }
+
+    /// Returns the path libbpf pinned this map at, see [`Map::is_pinned()`]. Always `None`
+    /// for maps that were not loaded from an [`Object`].
+    pub fn pin_path(&self) -> Option<&Path> {
+        if self.ptr.is_null() {
+            return None;
+        }
+
+        let path = unsafe { libbpf_sys::bpf_map__pin_path(self.ptr) };
+        if path.is_null() {
+            return None;
+        }
+
+        let path = unsafe { CStr::from_ptr(path) };
+        Some(Path::new(OsStr::from_bytes(path.to_bytes())))
+    }
+
     /// [Unpin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// from bpffs
     pub fn unpin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        if self.ptr.is_null() {
+            return std::fs::remove_file(path).map_err(util::io_error_to_error);
+        }
+
         let path_c = util::path_to_cstring(path)?;
         let path_ptr = path_c.as_ptr();
 
@@ -318,6 +1417,320 @@ impl Map {
             Ok(())
         }
     }
+
+    /// Pin this map to `path`, atomically replacing whatever is pinned there.
+    ///
+    /// The map is pinned to a temporary file next to `path` first, which is then renamed
+    /// over `path`. Users opening `path` see either the old or the new map, never no map.
+    /// Programs and processes already holding a file descriptor to the old map keep using
+    /// the old map.
+    pub fn pin_replace<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| Error::InvalidInput(format!("{} is not a file", path.display())))?;
+        // bpffs rejects names containing dots
+        let mut tmp_name = file_name.to_os_string();
+        tmp_name.push(format!("_tmp_{}", std::process::id()));
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let tmp_path_c = util::path_to_cstring(&tmp_path)?;
+        let ret = unsafe { libbpf_sys::bpf_obj_pin(self.fd, tmp_path_c.as_ptr()) };
+        if ret != 0 {
+            // Error code is returned negative, flip to positive to match errno
+            return Err(Error::System(-ret));
+        }
+
+        if let Err(e) = std::fs::rename(&tmp_path, path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(util::io_error_to_error(e));
+        }
+
+        Ok(())
+    }
+
+    /// Write a zeroed value to every index of an array map.
+    ///
+    /// Elements of array maps cannot be deleted, so this is how they are cleared. All indices
+    /// are written with a single batched update where the kernel supports it, otherwise one
+    /// index at a time.
+    ///
+    /// Only [`MapType::Array`] and [`MapType::PercpuArray`] are supported, other map types
+    /// return [`Error::InvalidInput`].
+    pub fn zero_all(&self) -> Result<()> {
+        let value_size = match self.map_type() {
+            MapType::Array => self.value_size as usize,
+            MapType::PercpuArray => {
+                let ncpus = wrappers::num_possible_cpus()?;
+                // Per-cpu values are laid out 8 byte aligned, one per possible cpu
+                ((self.value_size as usize + 7) & !7) * ncpus
+            }
+            _ => {
+                return Err(Error::InvalidInput(format!(
+                    "{} is not an array map",
+                    self.name
+                )))
+            }
+        };
+
+        let max_entries = self.max_entries;
+        let keys: Vec<u32> = (0..max_entries).collect();
+        let values = vec![0u8; value_size * keys.len()];
+
+        let opts = libbpf_sys::bpf_map_batch_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_map_batch_opts>() as libbpf_sys::size_t,
+            ..Default::default()
+        };
+        let mut count = max_entries;
+        let ret = unsafe {
+            libbpf_sys::bpf_map_update_batch(
+                self.fd,
+                keys.as_ptr() as *const c_void,
+                values.as_ptr() as *const c_void,
+                &mut count,
+                &opts,
+            )
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+
+        // Only fall back if the kernel lacks batch support
+        if let err @ Error::System(_) = batch_error(-ret) {
+            return Err(err);
+        }
+
+        for key in &keys {
+            let ret = unsafe {
+                libbpf_sys::bpf_map_update_elem(
+                    self.fd,
+                    key as *const u32 as *const c_void,
+                    values.as_ptr() as *const c_void,
+                    0,
+                )
+            };
+            if ret != 0 {
+                return Err(Error::System(errno::errno()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove all entries of this map.
+    ///
+    /// Array maps can't delete elements, so [`MapType::Array`] and [`MapType::PercpuArray`]
+    /// maps are zeroed with [`Map::zero_all()`] instead. For other maps the first key is
+    /// deleted until none is left, so entries added concurrently may be removed as well.
+    pub fn clear(&self) -> Result<()> {
+        if matches!(self.map_type(), MapType::Array | MapType::PercpuArray) {
+            return self.zero_all();
+        }
+
+        let mut key = vec![0u8; self.key_size as usize];
+        loop {
+            let ret = unsafe {
+                libbpf_sys::bpf_map_get_next_key(
+                    self.fd,
+                    ptr::null(),
+                    key.as_mut_ptr() as *mut c_void,
+                )
+            };
+            if ret != 0 {
+                let errno = errno::errno();
+                if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                    return Ok(());
+                }
+                return Err(Error::System(errno));
+            }
+
+            let ret =
+                unsafe { libbpf_sys::bpf_map_delete_elem(self.fd, key.as_ptr() as *const c_void) };
+            // The key may have been deleted concurrently
+            if ret != 0 && errno::Errno::from_i32(errno::errno()) != errno::Errno::ENOENT {
+                return Err(Error::System(errno::errno()));
+            }
+        }
+    }
+
+    /// Register this [`MapType::StructOps`] map, e.g. a TCP congestion control algorithm, with
+    /// the kernel. The implementation is unregistered when the returned [`Link`] is dropped.
+    ///
+    /// Maps defined in a `.struct_ops.link` section can later be swapped live with
+    /// [`Link::update_map()`].
+    pub fn attach_struct_ops(&mut self) -> Result<Link> {
+        if self.map_type() != MapType::StructOps {
+            return Err(Error::InvalidInput(format!(
+                "{} is not a struct_ops map",
+                self.name
+            )));
+        }
+
+        let ptr = unsafe { libbpf_sys::bpf_map__attach_struct_ops(self.libbpf_map()?) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Maps the values of this array map into memory for reading. Reading values needs no
+    /// syscalls, which makes it cheap to read whole arrays repeatedly. BPF programs may update
+    /// the values at any time, so they are not exposed as a slice but read through
+    /// [`MapSlice::get()`] and [`MapSlice::to_vec()`].
+    ///
+    /// The map must be a [`MapType::Array`] created with [`MapCreateFlags::MMAPABLE`], and
+    /// `V` must be exactly [`MapOps::value_size()`] bytes. The kernel pads array values to a
+    /// multiple of 8 bytes, so arrays with more than one entry also need a value size that is
+    /// a multiple of 8. The memory is unmapped when the returned [`MapSlice`] is dropped.
+    pub fn as_slice<V: Plain>(&self) -> Result<MapSlice<'_, V>> {
+        self.check_mmapable()?;
+
+        if mem::size_of::<V>() != self.value_size as usize {
+            return Err(Error::InvalidInput(format!(
+                "value type is {} bytes, but map {} has value size {}",
+                mem::size_of::<V>(),
+                self.name,
+                self.value_size
+            )));
+        }
+
+        let max_entries = self.max_entries as usize;
+        if max_entries > 1 && self.value_size & 7 != 0 {
+            return Err(Error::InvalidInput(format!(
+                "map {} has value size {}, which the kernel pads to 8 bytes",
+                self.name, self.value_size
+            )));
+        }
+
+        let (addr, len) = self.mmap_raw(self.value_size as usize * max_entries, libc::PROT_READ)?;
+
+        Ok(MapSlice {
+            addr,
+            mapped_len: len,
+            entries: max_entries,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Maps the values of this array map into memory for reading and writing without
+    /// syscalls, e.g. for configuration shared with the BPF program or high-rate counters.
+    /// Values are accessed through [`MmapRegion::read_volatile()`] and
+    /// [`MmapRegion::write_volatile()`].
+    ///
+    /// The map must be a [`MapType::Array`] created with [`MapCreateFlags::MMAPABLE`]. The
+    /// region holds `max_entries` values, each padded to a multiple of 8 bytes like the kernel
+    /// stores them. Frozen maps, such as `.rodata` after the object is loaded, can't be mapped
+    /// writable, use [`Map::as_slice()`] for those. The memory is unmapped when the returned
+    /// [`MmapRegion`] is dropped.
+    pub fn mmap(&self) -> Result<MmapRegion<'_>> {
+        self.check_mmapable()?;
+
+        let stride = (self.value_size as usize + 7) & !7;
+        let size = stride * self.max_entries as usize;
+        let (addr, len) = self.mmap_raw(size, libc::PROT_READ | libc::PROT_WRITE)?;
+
+        Ok(MmapRegion {
+            addr,
+            mapped_len: len,
+            size,
+            _marker: PhantomData,
+        })
+    }
+
+    fn check_mmapable(&self) -> Result<()> {
+        if self.map_type() != MapType::Array
+            || !self.create_flags().contains(MapCreateFlags::MMAPABLE)
+        {
+            return Err(Error::InvalidInput(format!(
+                "{} is not a mmapable array map",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Maps `size` bytes of the map rounded up to whole pages, returns the address and the
+    /// mapped length.
+    fn mmap_raw(&self, size: usize, prot: i32) -> Result<(*mut c_void, usize)> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = (size + page_size - 1) & !(page_size - 1);
+        let addr = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, self.fd, 0) };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok((addr, len))
+    }
+
+    /// Returns the number of entries in this map, counted with `bpf_map_lookup_batch`.
+    ///
+    /// This needs far fewer syscalls than walking [`MapOps::keys`], which matters for large
+    /// hash maps. The count is approximate if the map is modified concurrently. Maps without
+    /// batch support are counted by walking their keys instead.
+    pub fn approximate_count(&self) -> Result<usize> {
+        const BATCH_SIZE: u32 = 1024;
+
+        let mut value_size = self.value_size as usize;
+        if matches!(
+            self.map_type(),
+            MapType::PercpuHash | MapType::PercpuArray | MapType::LruPercpuHash
+        ) {
+            let ncpus = wrappers::num_possible_cpus()?;
+            // Per-cpu values are laid out 8 byte aligned, one per possible cpu
+            value_size = ((value_size + 7) & !7) * ncpus;
+        }
+
+        let mut keys = vec![0u8; self.key_size as usize * BATCH_SIZE as usize];
+        let mut values = vec![0u8; value_size * BATCH_SIZE as usize];
+        // Hash maps use a u32 bucket as batch token, other maps a key
+        let token_size = std::cmp::max(self.key_size as usize, mem::size_of::<u32>());
+        let mut in_batch = vec![0u8; token_size];
+        let mut out_batch = vec![0u8; token_size];
+
+        let opts = libbpf_sys::bpf_map_batch_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_map_batch_opts>() as libbpf_sys::size_t,
+            ..Default::default()
+        };
+        let mut total = 0;
+        let mut first = true;
+        loop {
+            let mut count = BATCH_SIZE;
+            let ret = unsafe {
+                libbpf_sys::bpf_map_lookup_batch(
+                    self.fd,
+                    if first {
+                        ptr::null_mut()
+                    } else {
+                        in_batch.as_mut_ptr() as *mut c_void
+                    },
+                    out_batch.as_mut_ptr() as *mut c_void,
+                    keys.as_mut_ptr() as *mut c_void,
+                    values.as_mut_ptr() as *mut c_void,
+                    &mut count,
+                    &opts,
+                )
+            };
+            if ret != 0 && -ret != errno::Errno::ENOENT as i32 {
+                // Same fallback as `zero_all()` if the kernel lacks batch support
+                return match batch_error(-ret) {
+                    Error::Unsupported(_) if first => self.count_entries(),
+                    err => Err(err),
+                };
+            }
+
+            total += count as usize;
+            // ENOENT signals that the last batch has been returned
+            if ret != 0 {
+                return Ok(total);
+            }
+
+            mem::swap(&mut in_batch, &mut out_batch);
+            first = false;
+        }
+    }
 }
 
 impl MapOps for Map {
@@ -344,17 +1757,226 @@ impl MapOps for Map {
         self.value_size
     }
 
+    fn create_flags(&self) -> MapCreateFlags {
+        MapCreateFlags::from_bits_truncate(self.map_flags)
+    }
+
+    fn max_entries(&self) -> u32 {
+        self.max_entries
+    }
+
     fn keys(&self) -> MapKeyIter {
         MapKeyIter::new(self, self.key_size())
     }
 }
 
+impl Drop for Map {
+    fn drop(&mut self) {
+        // Maps loaded from an `Object` are closed by libbpf along with it.
+        if self.ptr.is_null() {
+            let _ = unistd::close(self.fd);
+        }
+    }
+}
+
+/// Typed view of a [`Map`] with keys of type `K` and values of type `V`.
+///
+/// The key and value sizes are checked once in [`TypedMap::new()`], so lookups and updates
+/// can't be made with buffers of the wrong size. `K` and `V` must match the C layout of the
+/// key and value types used by the BPF program, e.g. by being `#[repr(C)]`.
+pub struct TypedMap<'a, K, V> {
+    map: &'a dyn MapOps,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<'a, K: Plain, V: Plain> TypedMap<'a, K, V> {
+    /// Wraps `map`. Fails with [`Error::InvalidInput`] if `K` or `V` don't have the key or
+    /// value size of `map`, or if `map` is a per-CPU map.
+    pub fn new(map: &'a dyn MapOps) -> Result<Self> {
+        if mem::size_of::<K>() != map.key_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "key type is {} bytes, but map {} has key size {}",
+                mem::size_of::<K>(),
+                map.name(),
+                map.key_size()
+            )));
+        }
+        if mem::size_of::<V>() != map.value_size() as usize {
+            return Err(Error::InvalidInput(format!(
+                "value type is {} bytes, but map {} has value size {}",
+                mem::size_of::<V>(),
+                map.name(),
+                map.value_size()
+            )));
+        }
+        if map.map_type().is_per_cpu() {
+            return Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map",
+                map.name()
+            )));
+        }
+
+        Ok(TypedMap {
+            map,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the value for `key`, see [`MapOps::lookup()`].
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        let bytes = match self
+            .map
+            .lookup(unsafe { plain::as_bytes(key) }, MapFlags::empty())?
+        {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        // Any bit pattern is a valid `Plain` value
+        let mut value: V = unsafe { mem::zeroed() };
+        plain::copy_from_bytes(&mut value, &bytes)
+            .map_err(|e| Error::Internal(format!("invalid value: {:?}", e)))?;
+        Ok(Some(value))
+    }
+
+    /// Sets the value for `key` to `value`, see [`MapOps::update()`].
+    pub fn set(&self, key: &K, value: &V, flags: MapFlags) -> Result<()> {
+        self.map.update(
+            unsafe { plain::as_bytes(key) },
+            unsafe { plain::as_bytes(value) },
+            flags,
+        )
+    }
+
+    /// Deletes `key` from the map, see [`MapOps::delete()`].
+    pub fn remove(&self, key: &K) -> Result<()> {
+        self.map.delete(unsafe { plain::as_bytes(key) })
+    }
+}
+
+/// Values of an array map mapped into memory, see [`Map::as_slice()`].
+///
+/// The memory is shared with the kernel, so values updated by BPF programs or other processes
+/// show up without reading the map again. Every read goes to memory, but a value written
+/// while it is read may be seen half updated.
+pub struct MapSlice<'a, V> {
+    addr: *mut c_void,
+    mapped_len: usize,
+    entries: usize,
+    _marker: PhantomData<&'a V>,
+}
+
+impl<'a, V: Plain> MapSlice<'a, V> {
+    /// Returns the number of values, the `max_entries` of the map.
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    /// Returns whether the map has no values.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Reads the value at `index`, or returns `None` if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> Option<V> {
+        if index >= self.entries {
+            return None;
+        }
+
+        Some(unsafe { ptr::read_volatile((self.addr as *const V).add(index)) })
+    }
+
+    /// Copies all values out of the map.
+    pub fn to_vec(&self) -> Vec<V> {
+        (0..self.entries).filter_map(|i| self.get(i)).collect()
+    }
+}
+
+impl<'a, V> Drop for MapSlice<'a, V> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.mapped_len) };
+    }
+}
+
+/// The values of an array map mapped into memory, see [`Map::mmap()`]. Reads and writes go
+/// straight to the map and are seen by the BPF program right away.
+///
+/// BPF programs may access the memory at any time, so it is not exposed as a slice. Values
+/// are read and written with volatile accesses through [`MmapRegion::read_volatile()`] and
+/// [`MmapRegion::write_volatile()`]. A value written concurrently may be seen half updated,
+/// build atomics on [`MmapRegion::as_ptr()`] where that matters.
+pub struct MmapRegion<'a> {
+    addr: *mut c_void,
+    mapped_len: usize,
+    size: usize,
+    _marker: PhantomData<&'a Map>,
+}
+
+impl<'a> MmapRegion<'a> {
+    /// Returns the size of the region in bytes, `max_entries` values padded to 8 bytes each.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether the region is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns a pointer to the start of the region, valid for [`MmapRegion::len()`] bytes
+    /// while the region is alive. The region is page aligned.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.addr as *mut u8
+    }
+
+    /// Reads a `T` at byte `offset` of the region with a volatile read.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the `T` doesn't fit in the region or `offset`
+    /// isn't aligned for `T`.
+    pub fn read_volatile<T: Plain>(&self, offset: usize) -> Result<T> {
+        let ptr = self.ptr_at::<T>(offset)?;
+        Ok(unsafe { ptr::read_volatile(ptr) })
+    }
+
+    /// Writes `value` at byte `offset` of the region with a volatile write.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the `T` doesn't fit in the region or `offset`
+    /// isn't aligned for `T`.
+    pub fn write_volatile<T: Plain>(&mut self, offset: usize, value: T) -> Result<()> {
+        let ptr = self.ptr_at::<T>(offset)?;
+        unsafe { ptr::write_volatile(ptr, value) };
+        Ok(())
+    }
+
+    fn ptr_at<T>(&self, offset: usize) -> Result<*mut T> {
+        match offset.checked_add(mem::size_of::<T>()) {
+            Some(end) if end <= self.size && offset & (mem::align_of::<T>() - 1) == 0 => {
+                Ok(unsafe { self.as_ptr().add(offset) } as *mut T)
+            }
+            _ => Err(Error::InvalidInput(format!(
+                "{} bytes at offset {} are misaligned or past the end of the {} byte region",
+                mem::size_of::<T>(),
+                offset,
+                self.size
+            ))),
+        }
+    }
+}
+
+impl<'a> Drop for MmapRegion<'a> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.mapped_len) };
+    }
+}
+
 pub struct PinnedMap {
     fd: i32,
     name: String,
     ty: libbpf_sys::bpf_map_type,
     key_size: u32,
     value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
 }
 
 impl PinnedMap {
@@ -379,6 +2001,8 @@ impl PinnedMap {
             ty: info.type_,
             key_size: info.key_size,
             value_size: info.value_size,
+            max_entries: info.max_entries,
+            map_flags: info.map_flags,
         })
     }
 }
@@ -407,6 +2031,14 @@ impl MapOps for PinnedMap {
         self.value_size
     }
 
+    fn create_flags(&self) -> MapCreateFlags {
+        MapCreateFlags::from_bits_truncate(self.map_flags)
+    }
+
+    fn max_entries(&self) -> u32 {
+        self.max_entries
+    }
+
     fn keys(&self) -> MapKeyIter {
         MapKeyIter::new(self, self.key_size())
     }
@@ -429,10 +2061,31 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Flags a [`Map`] was created with. Maps to the `BPF_F_*` map creation flags in kernel
+    /// uapi.
+    #[derive(Default)]
+    pub struct MapCreateFlags: u32 {
+        const NO_PREALLOC    = 1;
+        const NO_COMMON_LRU  = 1 << 1;
+        const NUMA_NODE      = 1 << 2;
+        const RDONLY         = 1 << 3;
+        const WRONLY         = 1 << 4;
+        const STACK_BUILD_ID = 1 << 5;
+        const ZERO_SEED      = 1 << 6;
+        const RDONLY_PROG    = 1 << 7;
+        const WRONLY_PROG    = 1 << 8;
+        const CLONE          = 1 << 9;
+        const MMAPABLE       = 1 << 10;
+        const PRESERVE_ELEMS = 1 << 11;
+        const INNER_MAP      = 1 << 12;
+    }
+}
+
 /// Type of a [`Map`]. Maps to `enum bpf_map_type` in kernel uapi.
 #[non_exhaustive]
 #[repr(u32)]
-#[derive(Clone, TryFromPrimitive, PartialEq, Display)]
+#[derive(Clone, Debug, TryFromPrimitive, PartialEq, Display)]
 pub enum MapType {
     Unspec = 0,
     Hash,
@@ -462,12 +2115,94 @@ pub enum MapType {
     DevmapHash,
     StructOps,
     RingBuf,
+    InodeStorage,
+    TaskStorage,
+    BloomFilter,
     /// We choose to specify our own "unknown" type here b/c it's really up to the kernel
     /// to decide if it wants to reject the map. If it accepts it, it just means whoever
     /// using this library is a bit out of date.
     Unknown = u32::MAX,
 }
 
+impl MapType {
+    /// Whether maps of this type hold one value per possible CPU for each key.
+    pub fn is_per_cpu(&self) -> bool {
+        matches!(
+            self,
+            MapType::PercpuHash
+                | MapType::PercpuArray
+                | MapType::LruPercpuHash
+                | MapType::PercpuCgroupStorage
+        )
+    }
+
+    /// Whether elements of maps of this type have no key, so maps are created with a key size
+    /// of 0 and values are accessed on their own, like with [`MapOps::push()`] or
+    /// [`MapOps::bloom_add()`].
+    pub fn is_keyless(&self) -> bool {
+        matches!(self, MapType::Queue | MapType::Stack | MapType::BloomFilter)
+    }
+
+    /// Whether maps of this type hold other maps, see [`MapOps::update_inner_map()`].
+    pub fn is_map_of_maps(&self) -> bool {
+        matches!(self, MapType::ArrayOfMaps | MapType::HashOfMaps)
+    }
+
+    /// Canonical name of this map type, as used by libbpf and bpftool (e.g. `hash`).
+    /// Returns `"unknown"` for [`MapType::Unknown`].
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            MapType::Unspec => "unspec",
+            MapType::Hash => "hash",
+            MapType::Array => "array",
+            MapType::ProgArray => "prog_array",
+            MapType::PerfEventArray => "perf_event_array",
+            MapType::PercpuHash => "percpu_hash",
+            MapType::PercpuArray => "percpu_array",
+            MapType::StackTrace => "stack_trace",
+            MapType::CgroupArray => "cgroup_array",
+            MapType::LruHash => "lru_hash",
+            MapType::LruPercpuHash => "lru_percpu_hash",
+            MapType::LpmTrie => "lpm_trie",
+            MapType::ArrayOfMaps => "array_of_maps",
+            MapType::HashOfMaps => "hash_of_maps",
+            MapType::Devmap => "devmap",
+            MapType::Sockmap => "sockmap",
+            MapType::Cpumap => "cpumap",
+            MapType::Xskmap => "xskmap",
+            MapType::Sockhash => "sockhash",
+            MapType::CgroupStorage => "cgroup_storage",
+            MapType::ReuseportSockarray => "reuseport_sockarray",
+            MapType::PercpuCgroupStorage => "percpu_cgroup_storage",
+            MapType::Queue => "queue",
+            MapType::Stack => "stack",
+            MapType::SkStorage => "sk_storage",
+            MapType::DevmapHash => "devmap_hash",
+            MapType::StructOps => "struct_ops",
+            MapType::RingBuf => "ringbuf",
+            MapType::InodeStorage => "inode_storage",
+            MapType::TaskStorage => "task_storage",
+            MapType::BloomFilter => "bloom_filter",
+            MapType::Unknown => "unknown",
+        }
+    }
+
+    /// Parse a canonical map type name as returned by [`MapType::kernel_name`]. Returns
+    /// `None` if `name` is not recognized.
+    pub fn from_kernel_name(name: &str) -> Option<Self> {
+        // Map types are contiguous, stop at the first value without a variant
+        let mut i = 0;
+        while let Ok(ty) = MapType::try_from(i) {
+            if ty.kernel_name() == name {
+                return Some(ty);
+            }
+            i += 1;
+        }
+
+        None
+    }
+}
+
 pub struct MapKeyIter<'a> {
     map: &'a dyn MapOps,
     prev: Option<Vec<u8>>,
@@ -482,22 +2217,93 @@ impl<'a> MapKeyIter<'a> {
             next: vec![0; key_size as usize],
         }
     }
-}
-
-impl<'a> Iterator for MapKeyIter<'a> {
-    type Item = Vec<u8>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Reads the key after the previous one, `Ok(None)` once there are no keys left.
+    fn next_key(&mut self) -> Result<Option<Vec<u8>>> {
         let prev = self.prev.as_ref().map_or(ptr::null(), |p| p.as_ptr());
 
         let ret = unsafe {
             libbpf_sys::bpf_map_get_next_key(self.map.fd(), prev as _, self.next.as_mut_ptr() as _)
         };
         if ret != 0 {
-            None
-        } else {
-            self.prev = Some(self.next.clone());
-            Some(self.next.clone())
+            let errno = errno::errno();
+            if errno::Errno::from_i32(errno) == errno::Errno::ENOENT {
+                return Ok(None);
+            }
+            return Err(Error::System(errno));
+        }
+
+        self.prev = Some(self.next.clone());
+        Ok(Some(self.next.clone()))
+    }
+}
+
+impl<'a> Iterator for MapKeyIter<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_key().ok().flatten()
+    }
+}
+
+/// Iterator over the keys of a map that reports errors, see [`MapOps::try_keys()`].
+pub struct MapTryKeyIter<'a> {
+    keys: MapKeyIter<'a>,
+    done: bool,
+}
+
+impl<'a> Iterator for MapTryKeyIter<'a> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let ret = self.keys.next_key().transpose();
+        if !matches!(ret, Some(Ok(_))) {
+            self.done = true;
+        }
+        ret
+    }
+}
+
+/// Iterator over `(key, value)` pairs of a map, see [`MapOps::iter()`].
+pub struct MapIter<'a> {
+    keys: MapTryKeyIter<'a>,
+}
+
+impl<'a> Iterator for MapIter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.keys.done {
+            return None;
+        }
+
+        let map = self.keys.keys.map;
+        if map.map_type().is_per_cpu() {
+            self.keys.done = true;
+            return Some(Err(Error::InvalidInput(format!(
+                "{} is a per-CPU map, values must be looked up per CPU",
+                map.name()
+            ))));
+        }
+
+        loop {
+            let key = match self.keys.next()? {
+                Ok(key) => key,
+                Err(err) => return Some(Err(err)),
+            };
+            match map.lookup(&key, MapFlags::empty()) {
+                Ok(Some(value)) => return Some(Ok((key, value))),
+                // Deleted since `bpf_map_get_next_key()` returned it
+                Ok(None) => continue,
+                Err(err) => {
+                    self.keys.done = true;
+                    return Some(Err(err));
+                }
+            }
         }
     }
 }
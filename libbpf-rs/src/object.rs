@@ -6,6 +6,7 @@ use std::os::raw::c_char;
 use std::path::Path;
 use std::ptr;
 
+use crate::program::is_gpl_violation;
 use crate::util;
 use crate::*;
 
@@ -23,6 +24,28 @@ impl ObjectBuilder {
         self
     }
 
+    /// Same as [`ObjectBuilder::name()`], but validates `name` first. `name` must be a C
+    /// identifier that may also contain `.`: only ASCII alphanumerics, `_` and `.` are
+    /// accepted, and it must not start with a digit. This is stricter than the kernel, which
+    /// also accepts a leading digit.
+    ///
+    /// The name also applies to objects opened with [`ObjectBuilder::open_memory()`] without
+    /// a name of their own.
+    pub fn set_object_name(&mut self, name: &str) -> Result<&mut Self> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        if name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(valid_char)
+        {
+            return Err(Error::InvalidInput(format!(
+                "{:?} is not a valid object name",
+                name
+            )));
+        }
+
+        Ok(self.name(name))
+    }
+
     /// Option to parse map definitions non-strictly, allowing extra attributes/data
     pub fn relaxed_maps(&mut self, relaxed_maps: bool) -> &mut Self {
         self.relaxed_maps = relaxed_maps;
@@ -36,44 +59,23 @@ impl ObjectBuilder {
 
     /// Option to print debug output to stderr.
     pub fn debug(&mut self, dbg: bool) -> &mut Self {
-        extern "C" fn cb(
-            _level: libbpf_sys::libbpf_print_level,
-            fmtstr: *const c_char,
-            va_list: *mut libbpf_sys::__va_list_tag,
-        ) -> i32 {
-            match unsafe { vsprintf::vsprintf(fmtstr, va_list) } {
-                Ok(s) => {
-                    print!("{}", s);
-                    0
-                }
-                Err(e) => {
-                    eprintln!("Failed to parse libbpf output: {}", e);
-                    1
-                }
-            }
-        }
-
-        if dbg {
-            unsafe { libbpf_sys::libbpf_set_print(Some(cb)) };
-        } else {
-            unsafe { libbpf_sys::libbpf_set_print(None) };
-        }
-
+        init::set_print(dbg);
         self
     }
 
     /// Used for skeleton -- an end user may not consider this API stable
     #[doc(hidden)]
     pub fn opts(&mut self, name: *const c_char) -> libbpf_sys::bpf_object_open_opts {
-        let pin_root_path_ptr = self.pin_root_path.as_ref().map_or(ptr::null(), |p| { p.as_ptr() });
+        let pin_root_path_ptr = self
+            .pin_root_path
+            .as_ref()
+            .map_or(ptr::null(), |p| p.as_ptr());
         libbpf_sys::bpf_object_open_opts {
             sz: mem::size_of::<libbpf_sys::bpf_object_open_opts>() as libbpf_sys::size_t,
             object_name: name,
             relaxed_maps: self.relaxed_maps,
-            relaxed_core_relocs: false,
             pin_root_path: pin_root_path_ptr,
-            attach_prog_fd: 0,
-            kconfig: ptr::null(),
+            ..Default::default()
         }
     }
 
@@ -106,11 +108,20 @@ impl ObjectBuilder {
         OpenObject::new(obj)
     }
 
+    /// Opens an object from the ELF data in `mem`, e.g. an object embedded in the binary with
+    /// `include_bytes!`, without going through the file system.
+    ///
+    /// `name` names the object, an empty name falls back to the one set with
+    /// [`ObjectBuilder::set_object_name()`]. libbpf copies what it needs while opening, so `mem`
+    /// does not have to outlive the returned [`OpenObject`].
     pub fn open_memory<T: AsRef<str>>(&mut self, name: T, mem: &[u8]) -> Result<OpenObject> {
         // Convert name to a C style pointer
         //
         // NB: we must hold onto a CString otherwise our pointer dangles
-        let name = util::str_to_cstring(name.as_ref())?;
+        let name = match name.as_ref() {
+            "" => util::str_to_cstring(&self.name)?,
+            name => util::str_to_cstring(name)?,
+        };
         let name_ptr = if !name.to_bytes().is_empty() {
             name.as_ptr()
         } else {
@@ -166,7 +177,7 @@ impl OpenObject {
         let mut map: *mut libbpf_sys::bpf_map = std::ptr::null_mut();
         loop {
             // Get the pointer to the next BPF map
-            let next_ptr = unsafe { libbpf_sys::bpf_map__next(map, obj.ptr) };
+            let next_ptr = unsafe { libbpf_sys::bpf_object__next_map(obj.ptr, map) };
             if next_ptr.is_null() {
                 break;
             }
@@ -186,7 +197,7 @@ impl OpenObject {
         let mut prog: *mut libbpf_sys::bpf_program = std::ptr::null_mut();
         loop {
             // Get the pointer to the next BPF program
-            let next_ptr = unsafe { libbpf_sys::bpf_program__next(prog, obj.ptr) };
+            let next_ptr = unsafe { libbpf_sys::bpf_object__next_program(obj.ptr, prog) };
             if next_ptr.is_null() {
                 break;
             }
@@ -218,6 +229,11 @@ impl OpenObject {
 
     /// Takes underlying `libbpf_sys::bpf_object` pointer.
     pub fn take_ptr(mut self) -> *mut libbpf_sys::bpf_object {
+        // Log buffers are owned by the `OpenProgram`s, which are about to go away
+        for prog in self.progs.values_mut() {
+            prog.clear_log_buf();
+        }
+
         let ptr = self.ptr;
         self.ptr = ptr::null_mut();
         ptr
@@ -261,6 +277,47 @@ impl OpenObject {
         self.maps.values_mut()
     }
 
+    /// Returns the definitions of all maps declared in this object, in declaration order.
+    ///
+    /// This only inspects the parsed object and has no side effects. Note that this will include
+    /// automatically generated .data, .rodata, .bss, and .kconfig maps.
+    pub fn map_definitions(&self) -> Result<Vec<MapDef>> {
+        let mut defs = Vec::new();
+        let mut map: *mut libbpf_sys::bpf_map = std::ptr::null_mut();
+        loop {
+            map = unsafe { libbpf_sys::bpf_object__next_map(self.ptr, map) };
+            if map.is_null() {
+                break;
+            }
+            defs.push(MapDef::from_ptr(map)?);
+        }
+
+        Ok(defs)
+    }
+
+    /// Returns the name and section of all programs in this object, in the order they appear
+    /// in the object file.
+    ///
+    /// Like [`OpenObject::map_definitions`], this only inspects the parsed object. It can be
+    /// used to decide which programs to load, see [`OpenProgram::set_autoload`].
+    pub fn program_sections(&self) -> Result<Vec<(String, String)>> {
+        let mut sections = Vec::new();
+        let mut prog: *mut libbpf_sys::bpf_program = std::ptr::null_mut();
+        loop {
+            prog = unsafe { libbpf_sys::bpf_object__next_program(self.ptr, prog) };
+            if prog.is_null() {
+                break;
+            }
+
+            let name = util::c_ptr_to_string(unsafe { libbpf_sys::bpf_program__name(prog) })?;
+            let section =
+                util::c_ptr_to_string(unsafe { libbpf_sys::bpf_program__section_name(prog) })?;
+            sections.push((name, section));
+        }
+
+        Ok(sections)
+    }
+
     /// Get a reference to `OpenProgram` with the name `name`, if one exists.
     pub fn prog<T: AsRef<str>>(&self, name: T) -> Option<&OpenProgram> {
         self.progs.get(name.as_ref())
@@ -282,20 +339,83 @@ impl OpenObject {
     }
 
     /// Load the maps and programs contained in this BPF object into the system.
+    ///
+    /// If a program fails to load and the kernel logged why, this returns [`Error::Verifier`]
+    /// with the errno and the verifier log of that program. Other failures, e.g. failing to
+    /// create a map, return [`Error::System`]. To capture that log, every program gets a log
+    /// buffer that libbpf fills when it retries a failed load, so libbpf no longer prints the
+    /// log itself. [`OpenProgram::set_log_level`] also captures the log of successful loads.
+    ///
+    /// The license of all programs comes from the object's `license` section, libbpf offers
+    /// no way to override it. If a program fails to load because it calls GPL-only helpers
+    /// and that license is not GPL compatible, this fails with [`Error::InvalidInput`] naming
+    /// the program.
     pub fn load(mut self) -> Result<Object> {
+        for prog in self.progs.values_mut() {
+            if prog.autoload() {
+                prog.ensure_log_buf()?;
+            }
+        }
+
         let ret = unsafe { libbpf_sys::bpf_object__load(self.ptr) };
         if ret != 0 {
+            if let Some((name, log)) = self.failed_prog_log() {
+                if is_gpl_violation(&log) {
+                    return Err(Error::InvalidInput(format!(
+                        "program {} calls GPL-only helpers but the object's license is not GPL compatible",
+                        name
+                    )));
+                }
+
+                return Err(Error::Verifier {
+                    errno: -ret,
+                    log: format!("{}:\n{}", name, log),
+                });
+            }
+
             // bpf_object__load() returns errno as negative, so flip
             return Err(Error::System(-ret));
         }
 
         let obj = Object::new(self.ptr)?;
 
+        // Verifier logs were copied out above, detach the buffers before they are freed
+        for prog in self.progs.values_mut() {
+            prog.clear_log_buf();
+        }
+
         // Prevent object from being closed once `self` is dropped
         self.ptr = ptr::null_mut();
 
         Ok(obj)
     }
+
+    /// Returns the name and verifier log of the program that failed to load, if it left a log.
+    ///
+    /// libbpf loads programs in order and stops at the first one that fails, so that one has
+    /// the last log captured. Programs before it only have a log if a log level was set, and
+    /// the ones after it were never loaded.
+    fn failed_prog_log(&self) -> Option<(&str, String)> {
+        let mut failed = None;
+        let mut prog: *mut libbpf_sys::bpf_program = ptr::null_mut();
+        loop {
+            prog = unsafe { libbpf_sys::bpf_object__next_program(self.ptr, prog) };
+            if prog.is_null() {
+                return failed;
+            }
+
+            let name = unsafe { CStr::from_ptr(libbpf_sys::bpf_program__name(prog)) };
+            let open_prog = name
+                .to_str()
+                .ok()
+                .and_then(|name| self.progs.get_key_value(name));
+            if let Some((name, open_prog)) = open_prog {
+                if let Some(log) = open_prog.captured_log() {
+                    failed = Some((name.as_str(), log));
+                }
+            }
+        }
+    }
 }
 
 impl Drop for OpenObject {
@@ -318,6 +438,7 @@ impl Drop for OpenObject {
 /// enforcing this invariant.
 pub struct Object {
     ptr: *mut libbpf_sys::bpf_object,
+    name: String,
     maps: HashMap<String, Map>,
     progs: HashMap<String, Program>,
 }
@@ -326,6 +447,7 @@ impl Object {
     fn new(ptr: *mut libbpf_sys::bpf_object) -> Result<Self> {
         let mut obj = Object {
             ptr,
+            name: util::c_ptr_to_string(unsafe { libbpf_sys::bpf_object__name(ptr) })?,
             maps: HashMap::new(),
             progs: HashMap::new(),
         };
@@ -334,21 +456,33 @@ impl Object {
         let mut map: *mut libbpf_sys::bpf_map = std::ptr::null_mut();
         loop {
             // Get the pointer to the next BPF map
-            let next_ptr = unsafe { libbpf_sys::bpf_map__next(map, obj.ptr) };
+            let next_ptr = unsafe { libbpf_sys::bpf_object__next_map(obj.ptr, map) };
             if next_ptr.is_null() {
                 break;
             }
 
+            // Skip maps that were not created
+            let create = unsafe { libbpf_sys::bpf_map__autocreate(next_ptr) };
+            if !create {
+                map = next_ptr;
+                continue;
+            }
+
             // Get the map name
             // bpf_map__name can return null but only if it's passed a null.
             // We already know next_ptr is not null.
             let name = unsafe { libbpf_sys::bpf_map__name(next_ptr) };
             let name = util::c_ptr_to_string(name)?;
 
-            // Get the map def
-            // bpf_map__def can return null but only if it's passed a null.
-            // We already know next_ptr is not null.
-            let def = unsafe { ptr::read(libbpf_sys::bpf_map__def(next_ptr)) };
+            // Get the map type, key/value sizes, max entries and flags
+            let info = libbpf_sys::bpf_map_info {
+                type_: unsafe { libbpf_sys::bpf_map__type(next_ptr) },
+                key_size: unsafe { libbpf_sys::bpf_map__key_size(next_ptr) },
+                value_size: unsafe { libbpf_sys::bpf_map__value_size(next_ptr) },
+                max_entries: unsafe { libbpf_sys::bpf_map__max_entries(next_ptr) },
+                map_flags: unsafe { libbpf_sys::bpf_map__map_flags(next_ptr) },
+                ..Default::default()
+            };
 
             // Get the map fd
             let fd = unsafe { libbpf_sys::bpf_map__fd(next_ptr) };
@@ -357,10 +491,8 @@ impl Object {
             }
 
             // Add the map to the hashmap
-            obj.maps.insert(
-                name.clone(),
-                Map::new(fd, name, def.type_, def.key_size, def.value_size, next_ptr),
-            );
+            obj.maps
+                .insert(name.clone(), Map::new(fd, name, &info, next_ptr));
             map = next_ptr;
         }
 
@@ -368,7 +500,7 @@ impl Object {
         let mut prog: *mut libbpf_sys::bpf_program = std::ptr::null_mut();
         loop {
             // Get the pointer to the next BPF program
-            let next_ptr = unsafe { libbpf_sys::bpf_program__next(prog, obj.ptr) };
+            let next_ptr = unsafe { libbpf_sys::bpf_object__next_program(obj.ptr, prog) };
             if next_ptr.is_null() {
                 break;
             }
@@ -396,9 +528,20 @@ impl Object {
                 return Err(Error::System(-fd));
             }
 
+            // Copy out the verifier log, if a log buffer was set up. The buffer itself is owned
+            // by the `OpenProgram` and does not outlive the load.
+            let mut log_size: libbpf_sys::size_t = 0;
+            let log = unsafe { libbpf_sys::bpf_program__log_buf(next_ptr, &mut log_size) };
+            // Programs without a log level only log failed loads, so their buffer stays empty
+            let log = if log.is_null() || unsafe { *log } == 0 {
+                None
+            } else {
+                Some(util::c_ptr_to_string(log)?)
+            };
+
             // Add the program to the hashmap
             obj.progs
-                .insert(name.clone(), Program::new(next_ptr, name, section));
+                .insert(name.clone(), Program::new(next_ptr, name, section, log));
             prog = next_ptr;
         }
 
@@ -417,6 +560,17 @@ impl Object {
         Self::new(ptr)
     }
 
+    /// Name of this object, as set with [`ObjectBuilder::name()`] or inferred by libbpf from
+    /// the file name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// BTF of this object, if it has any.
+    pub fn btf(&self) -> Option<Btf<'_>> {
+        Btf::from_object(self.ptr)
+    }
+
     /// Get a reference to `Map` with the name `name`, if one exists.
     pub fn map<T: AsRef<str>>(&self, name: T) -> Option<&Map> {
         self.maps.get(name.as_ref())
@@ -460,6 +614,104 @@ impl Object {
     pub fn progs_iter_mut(&mut self) -> impl Iterator<Item = &mut Program> {
         self.progs.values_mut()
     }
+
+    /// Bind the maps and programs named by `S` into a new `S`.
+    ///
+    /// Returns [`Error::InvalidInput`] if any name listed in [`ObjectBinding::MAPS`] or
+    /// [`ObjectBinding::PROGS`] is listed twice or is not present in this `Object`.
+    pub fn bind_to<'a, S: ObjectBinding<'a>>(&'a mut self) -> Result<S> {
+        if let Some(name) = first_duplicate(S::MAPS) {
+            return Err(Error::InvalidInput(format!("duplicate map name {}", name)));
+        }
+        if let Some(name) = first_duplicate(S::PROGS) {
+            return Err(Error::InvalidInput(format!(
+                "duplicate program name {}",
+                name
+            )));
+        }
+
+        let mut maps: Vec<Option<&'a mut Map>> = S::MAPS.iter().map(|_| None).collect();
+        for map in self.maps.values_mut() {
+            if let Some(i) = S::MAPS.iter().position(|name| *name == map.name()) {
+                maps[i] = Some(map);
+            }
+        }
+
+        let mut progs: Vec<Option<&'a mut Program>> = S::PROGS.iter().map(|_| None).collect();
+        for prog in self.progs.values_mut() {
+            if let Some(i) = S::PROGS.iter().position(|name| *name == prog.name()) {
+                progs[i] = Some(prog);
+            }
+        }
+
+        let maps = maps
+            .into_iter()
+            .zip(S::MAPS)
+            .map(|(map, name)| {
+                map.ok_or_else(|| Error::InvalidInput(format!("no map named {}", name)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let progs = progs
+            .into_iter()
+            .zip(S::PROGS)
+            .map(|(prog, name)| {
+                prog.ok_or_else(|| Error::InvalidInput(format!("no program named {}", name)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(S::from_parts(maps, progs))
+    }
+}
+
+fn first_duplicate<'a>(names: &[&'a str]) -> Option<&'a str> {
+    names
+        .iter()
+        .enumerate()
+        .find(|(i, name)| names[..*i].contains(name))
+        .map(|(_, name)| *name)
+}
+
+/// A user defined, skeleton-like view over some of the maps and programs of an [`Object`].
+///
+/// This is the runtime counterpart of the skeletons generated by libbpf-cargo: list the names
+/// you want and [`Object::bind_to`] looks them up for you.
+///
+/// ```no_run
+/// # use libbpf_rs::{Map, ObjectBinding, ObjectBuilder, Program};
+/// struct Runqslower<'a> {
+///     start: &'a mut Map,
+///     handle_wakeup: &'a mut Program,
+/// }
+///
+/// impl<'a> ObjectBinding<'a> for Runqslower<'a> {
+///     const MAPS: &'static [&'static str] = &["start"];
+///     const PROGS: &'static [&'static str] = &["handle__sched_wakeup"];
+///
+///     fn from_parts(maps: Vec<&'a mut Map>, progs: Vec<&'a mut Program>) -> Self {
+///         let mut maps = maps.into_iter();
+///         let mut progs = progs.into_iter();
+///         Runqslower {
+///             start: maps.next().unwrap(),
+///             handle_wakeup: progs.next().unwrap(),
+///         }
+///     }
+/// }
+///
+/// let mut obj = ObjectBuilder::default()
+///     .open_file("runqslower.bpf.o")?
+///     .load()?;
+/// let skel: Runqslower = obj.bind_to()?;
+/// # Ok::<(), libbpf_rs::Error>(())
+/// ```
+pub trait ObjectBinding<'a>: Sized {
+    /// Names of the maps to bind.
+    const MAPS: &'static [&'static str];
+    /// Names of the programs to bind.
+    const PROGS: &'static [&'static str];
+
+    /// Build `Self` out of the bound objects. `maps[i]` is the map named `MAPS[i]` and
+    /// `progs[i]` is the program named `PROGS[i]`.
+    fn from_parts(maps: Vec<&'a mut Map>, progs: Vec<&'a mut Program>) -> Self;
 }
 
 impl Drop for Object {
@@ -1,5 +1,6 @@
 use core::ffi::c_void;
 use std::boxed::Box;
+use std::ptr;
 use std::slice;
 use std::time::Duration;
 
@@ -106,22 +107,24 @@ impl<'a> PerfBufferBuilder<'a> {
             lost_cb: self.lost_cb,
         }));
 
-        let opts = libbpf_sys::perf_buffer_opts {
-            sample_cb: c_sample_cb,
-            lost_cb: c_lost_cb,
-            ctx: callback_struct_ptr as *mut _,
-        };
-
         let ptr = unsafe {
-            libbpf_sys::perf_buffer__new(self.map.fd(), self.pages as libbpf_sys::size_t, &opts)
+            libbpf_sys::perf_buffer__new(
+                self.map.fd(),
+                self.pages as libbpf_sys::size_t,
+                c_sample_cb,
+                c_lost_cb,
+                callback_struct_ptr as *mut _,
+                ptr::null(),
+            )
         };
+        let cb_struct = unsafe { Box::from_raw(callback_struct_ptr) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
         } else {
             Ok(PerfBuffer {
                 ptr,
-                _cb_struct: unsafe { Box::from_raw(callback_struct_ptr) },
+                _cb_struct: cb_struct,
             })
         }
     }
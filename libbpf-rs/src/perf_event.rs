@@ -0,0 +1,124 @@
+use std::mem;
+
+use nix::{errno, libc, unistd};
+
+use crate::*;
+
+/// Opens a perf event with `perf_event_open(2)`, e.g. to attach a
+/// [`ProgramType::PerfEvent`] program that samples the CPU with
+/// [`Program::attach_perf_event_opts`].
+///
+/// By default the event counts the calling process on any CPU and is sampled every event.
+pub struct PerfEventBuilder {
+    attr: libbpf_sys::perf_event_attr,
+    pid: i32,
+    cpu: i32,
+}
+
+impl PerfEventBuilder {
+    fn new(type_: u32, config: u64) -> Self {
+        let mut attr = libbpf_sys::perf_event_attr {
+            type_,
+            size: mem::size_of::<libbpf_sys::perf_event_attr>() as u32,
+            config,
+            ..Default::default()
+        };
+        attr.__bindgen_anon_1.sample_period = 1;
+        PerfEventBuilder {
+            attr,
+            pid: 0,
+            cpu: -1,
+        }
+    }
+
+    /// A hardware event, `config` is one of the `PERF_COUNT_HW_*` constants in
+    /// [`libbpf_sys`], e.g. `PERF_COUNT_HW_CPU_CYCLES`.
+    pub fn hardware(config: u64) -> Self {
+        Self::new(libbpf_sys::PERF_TYPE_HARDWARE, config)
+    }
+
+    /// A software event, `config` is one of the `PERF_COUNT_SW_*` constants in
+    /// [`libbpf_sys`], e.g. `PERF_COUNT_SW_CPU_CLOCK`.
+    pub fn software(config: u64) -> Self {
+        Self::new(libbpf_sys::PERF_TYPE_SOFTWARE, config)
+    }
+
+    /// Sample every `period` events.
+    pub fn sample_period(&mut self, period: u64) -> &mut Self {
+        self.attr.set_freq(0);
+        self.attr.__bindgen_anon_1.sample_period = period;
+        self
+    }
+
+    /// Sample `freq` times per second, the kernel adjusts the period to match.
+    pub fn sample_freq(&mut self, freq: u64) -> &mut Self {
+        self.attr.set_freq(1);
+        self.attr.__bindgen_anon_1.sample_freq = freq;
+        self
+    }
+
+    /// Only count the process or thread `pid`, 0 for the calling process or -1 for all
+    /// processes. Counting all processes requires a CPU to be set.
+    pub fn pid(&mut self, pid: i32) -> &mut Self {
+        self.pid = pid;
+        self
+    }
+
+    /// Only count on `cpu`, -1 for any CPU.
+    pub fn cpu(&mut self, cpu: i32) -> &mut Self {
+        self.cpu = cpu;
+        self
+    }
+
+    pub fn build(&self) -> Result<PerfEvent> {
+        if self.pid == -1 && self.cpu == -1 {
+            return Err(Error::InvalidInput(
+                "perf events for all processes need a cpu".to_string(),
+            ));
+        }
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &self.attr as *const libbpf_sys::perf_event_attr,
+                self.pid,
+                self.cpu,
+                -1,
+                libbpf_sys::PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(PerfEvent { fd: fd as i32 })
+    }
+}
+
+/// An open perf event, see [`PerfEventBuilder`]. The event is closed when dropped.
+pub struct PerfEvent {
+    fd: i32,
+}
+
+impl PerfEvent {
+    pub fn fd(&self) -> i32 {
+        self.fd
+    }
+
+    /// Releases ownership of the file descriptor.
+    ///
+    /// Use this to pass the event to [`Program::attach_perf_event`] or
+    /// [`Program::attach_perf_event_opts`]: the returned [`Link`] closes the event once it is
+    /// dropped.
+    pub fn into_fd(self) -> i32 {
+        let fd = self.fd;
+        mem::forget(self);
+        fd
+    }
+}
+
+impl Drop for PerfEvent {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.fd);
+    }
+}
@@ -1,10 +1,17 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::path::Path;
-use std::time::Duration;
 use std::ffi::c_void;
+use std::mem;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use nix::errno;
+use bitflags::bitflags;
+use nix::sched::{self, CpuSet};
+use nix::unistd::{self, Pid};
+use nix::{errno, libc};
 use num_enum::TryFromPrimitive;
 use strum_macros::Display;
 
@@ -15,11 +22,23 @@ use crate::*;
 /// This object exposes operations that need to happen before the program is loaded.
 pub struct OpenProgram {
     ptr: *mut libbpf_sys::bpf_program,
+    log_buf: Vec<u8>,
 }
 
+/// Size of the verifier log buffer allocated by [`OpenProgram::set_log_level`]. Matches the
+/// size libbpf itself uses when retrying a failed load, which is also the largest log the
+/// kernel accepts.
+const DEFAULT_LOG_BUF_SIZE: usize = (u32::MAX >> 8) as usize;
+
+/// Smallest verifier log buffer the kernel accepts.
+const MIN_LOG_BUF_SIZE: usize = 128;
+
 impl OpenProgram {
     pub(crate) fn new(ptr: *mut libbpf_sys::bpf_program) -> Self {
-        OpenProgram { ptr }
+        OpenProgram {
+            ptr,
+            log_buf: Vec::new(),
+        }
     }
 
     pub fn set_prog_type(&mut self, prog_type: ProgramType) {
@@ -40,6 +59,32 @@ impl OpenProgram {
         }
     }
 
+    /// Set the function this `fentry`, `fexit`, `tp_btf` or `freplace` program attaches to,
+    /// overriding the target given by its section name.
+    ///
+    /// `attach_prog_fd` is the fd of an already loaded program whose function
+    /// `attach_func_name` is traced or replaced, as needed for [`ProgramType::Ext`] programs.
+    /// Pass 0 to target a kernel function instead, which is looked up in the kernel BTF right
+    /// away. Must be called before the object is loaded.
+    pub fn set_attach_target(
+        &mut self,
+        attach_prog_fd: i32,
+        attach_func_name: Option<&str>,
+    ) -> Result<()> {
+        let name = match attach_func_name {
+            Some(name) => Some(util::str_to_cstring(name)?),
+            None => None,
+        };
+        let name_ptr = name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+        let ret = unsafe {
+            libbpf_sys::bpf_program__set_attach_target(self.ptr, attach_prog_fd, name_ptr)
+        };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+        Ok(())
+    }
+
     pub fn set_autoload(&mut self, autoload: bool) -> Result<()> {
         let ret = unsafe { libbpf_sys::bpf_program__set_autoload(self.ptr, autoload) };
         if ret < 0 {
@@ -51,6 +96,107 @@ impl OpenProgram {
     pub fn autoload(&mut self) -> bool {
         unsafe { libbpf_sys::bpf_program__autoload(self.ptr) }
     }
+
+    /// Set the verifier log level for this program. `1` logs the instructions the verifier
+    /// walked, `2` additionally logs the register state at every instruction.
+    ///
+    /// A non-zero level also sets up a buffer capturing the log. After a successful load it can
+    /// be read through [`Program::verifier_log`]; if loading fails, [`OpenObject::load`] returns
+    /// it in an [`Error::Verifier`].
+    ///
+    /// Note that high log levels significantly slow down loading.
+    pub fn set_log_level(&mut self, level: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_program__set_log_level(self.ptr, level) };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+
+        if level > 0 && self.log_buf.is_empty() {
+            self.alloc_log_buf(DEFAULT_LOG_BUF_SIZE)?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the size in bytes of the buffer capturing the verifier log, instead of the 16 MiB
+    /// [`OpenProgram::set_log_level`] allocates. `size` must be between 128 bytes and 16 MiB.
+    ///
+    /// The kernel fails the load with `ENOSPC` if the log doesn't fit, so the buffer must be
+    /// large enough for the chosen log level. Without a log level, the buffer is only filled
+    /// if loading fails.
+    pub fn set_log_buf_size(&mut self, size: usize) -> Result<()> {
+        if !(MIN_LOG_BUF_SIZE..=DEFAULT_LOG_BUF_SIZE).contains(&size) {
+            return Err(Error::InvalidInput(format!(
+                "log buffer size must be between {} and {} bytes",
+                MIN_LOG_BUF_SIZE, DEFAULT_LOG_BUF_SIZE
+            )));
+        }
+
+        self.alloc_log_buf(size)
+    }
+
+    fn alloc_log_buf(&mut self, size: usize) -> Result<()> {
+        self.log_buf = vec![0; size];
+        let ret = unsafe {
+            libbpf_sys::bpf_program__set_log_buf(
+                self.ptr,
+                self.log_buf.as_mut_ptr() as *mut _,
+                self.log_buf.len() as libbpf_sys::size_t,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_program__log_level(self.ptr) }
+    }
+
+    /// Set the `BPF_F_*` flags this program is loaded with, e.g. `BPF_F_SLEEPABLE` for
+    /// sleepable LSM or tracing programs that call helpers such as `bpf_copy_from_user`.
+    /// Replaces the flags libbpf derived from the section name, see [`OpenProgram::flags`].
+    /// Must be called before the object is loaded.
+    pub fn set_flags(&mut self, flags: u32) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_program__set_flags(self.ptr, flags) };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+        Ok(())
+    }
+
+    /// Returns the flags this program will be loaded with.
+    pub fn flags(&self) -> u32 {
+        unsafe { libbpf_sys::bpf_program__flags(self.ptr) }
+    }
+
+    /// Returns what the verifier wrote into the log buffer set up by
+    /// [`OpenProgram::set_log_level`] or [`OpenProgram::set_log_buf_size`], if anything.
+    pub(crate) fn captured_log(&self) -> Option<String> {
+        let len = self.log_buf.iter().position(|&b| b == 0)?;
+        if len == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&self.log_buf[..len]).into_owned())
+    }
+
+    /// Sets up a log buffer if the program doesn't have one yet. With no log level set, libbpf
+    /// only fills it when retrying a failed load.
+    pub(crate) fn ensure_log_buf(&mut self) -> Result<()> {
+        if self.log_buf.is_empty() {
+            self.alloc_log_buf(DEFAULT_LOG_BUF_SIZE)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn clear_log_buf(&mut self) {
+        if !self.log_buf.is_empty() {
+            unsafe { libbpf_sys::bpf_program__set_log_buf(self.ptr, ptr::null_mut(), 0) };
+            self.log_buf = Vec::new();
+        }
+    }
 }
 
 /// Type of a [`Program`]. Maps to `enum bpf_prog_type` in kernel uapi.
@@ -92,10 +238,65 @@ pub enum ProgramType {
     Unknown = u32::MAX,
 }
 
+impl ProgramType {
+    /// Canonical name of this program type, as used by libbpf and bpftool (e.g. `socket_filter`).
+    /// Returns `"unknown"` for [`ProgramType::Unknown`].
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            ProgramType::Unspec => "unspec",
+            ProgramType::SocketFilter => "socket_filter",
+            ProgramType::Kprobe => "kprobe",
+            ProgramType::SchedCls => "sched_cls",
+            ProgramType::SchedAct => "sched_act",
+            ProgramType::Tracepoint => "tracepoint",
+            ProgramType::Xdp => "xdp",
+            ProgramType::PerfEvent => "perf_event",
+            ProgramType::CgroupSkb => "cgroup_skb",
+            ProgramType::CgroupSock => "cgroup_sock",
+            ProgramType::LwtIn => "lwt_in",
+            ProgramType::LwtOut => "lwt_out",
+            ProgramType::LwtXmit => "lwt_xmit",
+            ProgramType::SockOps => "sock_ops",
+            ProgramType::SkSkb => "sk_skb",
+            ProgramType::CgroupDevice => "cgroup_device",
+            ProgramType::SkMsg => "sk_msg",
+            ProgramType::RawTracepoint => "raw_tracepoint",
+            ProgramType::CgroupSockAddr => "cgroup_sock_addr",
+            ProgramType::LwtSeg6local => "lwt_seg6local",
+            ProgramType::LircMode2 => "lirc_mode2",
+            ProgramType::SkReuseport => "sk_reuseport",
+            ProgramType::FlowDissector => "flow_dissector",
+            ProgramType::CgroupSysctl => "cgroup_sysctl",
+            ProgramType::RawTracepointWritable => "raw_tracepoint_writable",
+            ProgramType::CgroupSockopt => "cgroup_sockopt",
+            ProgramType::Tracing => "tracing",
+            ProgramType::StructOps => "struct_ops",
+            ProgramType::Ext => "ext",
+            ProgramType::Lsm => "lsm",
+            ProgramType::Unknown => "unknown",
+        }
+    }
+
+    /// Parse a canonical program type name as returned by [`ProgramType::kernel_name`]. Returns
+    /// `None` if `name` is not recognized.
+    pub fn from_kernel_name(name: &str) -> Option<Self> {
+        // Program types are contiguous, stop at the first value without a variant
+        let mut i = 0;
+        while let Ok(ty) = ProgramType::try_from(i) {
+            if ty.kernel_name() == name {
+                return Some(ty);
+            }
+            i += 1;
+        }
+
+        None
+    }
+}
+
 /// Attach type of a [`Program`]. Maps to `enum bpf_attach_type` in kernel uapi.
 #[non_exhaustive]
 #[repr(u32)]
-#[derive(Clone, TryFromPrimitive, Display)]
+#[derive(Clone, Debug, TryFromPrimitive, Display)]
 pub enum ProgramAttachType {
     CgroupInetIngress,
     CgroupInetEgress,
@@ -129,6 +330,225 @@ pub enum ProgramAttachType {
     Unknown = u32::MAX,
 }
 
+impl ProgramAttachType {
+    /// Canonical name of this attach type, as used by libbpf and bpftool (e.g.
+    /// `cgroup_inet_ingress`). Returns `"unknown"` for [`ProgramAttachType::Unknown`].
+    pub fn kernel_name(&self) -> &'static str {
+        match self {
+            ProgramAttachType::CgroupInetIngress => "cgroup_inet_ingress",
+            ProgramAttachType::CgroupInetEgress => "cgroup_inet_egress",
+            ProgramAttachType::CgroupInetSockCreate => "cgroup_inet_sock_create",
+            ProgramAttachType::CgroupSockOps => "cgroup_sock_ops",
+            ProgramAttachType::SkSkbStreamParser => "sk_skb_stream_parser",
+            ProgramAttachType::SkSkbStreamVerdict => "sk_skb_stream_verdict",
+            ProgramAttachType::CgroupDevice => "cgroup_device",
+            ProgramAttachType::SkMsgVerdict => "sk_msg_verdict",
+            ProgramAttachType::CgroupInet4Bind => "cgroup_inet4_bind",
+            ProgramAttachType::CgroupInet6Bind => "cgroup_inet6_bind",
+            ProgramAttachType::CgroupInet4Connect => "cgroup_inet4_connect",
+            ProgramAttachType::CgroupInet6Connect => "cgroup_inet6_connect",
+            ProgramAttachType::CgroupInet4PostBind => "cgroup_inet4_post_bind",
+            ProgramAttachType::CgroupInet6PostBind => "cgroup_inet6_post_bind",
+            ProgramAttachType::CgroupUdp4Sendmsg => "cgroup_udp4_sendmsg",
+            ProgramAttachType::CgroupUdp6Sendmsg => "cgroup_udp6_sendmsg",
+            ProgramAttachType::LircMode2 => "lirc_mode2",
+            ProgramAttachType::FlowDissector => "flow_dissector",
+            ProgramAttachType::CgroupSysctl => "cgroup_sysctl",
+            ProgramAttachType::CgroupUdp4Recvmsg => "cgroup_udp4_recvmsg",
+            ProgramAttachType::CgroupUdp6Recvmsg => "cgroup_udp6_recvmsg",
+            ProgramAttachType::CgroupGetsockopt => "cgroup_getsockopt",
+            ProgramAttachType::CgroupSetsockopt => "cgroup_setsockopt",
+            ProgramAttachType::TraceRawTp => "trace_raw_tp",
+            ProgramAttachType::TraceFentry => "trace_fentry",
+            ProgramAttachType::TraceFexit => "trace_fexit",
+            ProgramAttachType::ModifyReturn => "modify_return",
+            ProgramAttachType::LsmMac => "lsm_mac",
+            ProgramAttachType::Unknown => "unknown",
+        }
+    }
+
+    /// Parse a canonical attach type name as returned by
+    /// [`ProgramAttachType::kernel_name`]. Returns `None` if `name` is not recognized.
+    pub fn from_kernel_name(name: &str) -> Option<Self> {
+        // Attach types are contiguous, stop at the first value without a variant
+        let mut i = 0;
+        while let Ok(ty) = ProgramAttachType::try_from(i) {
+            if ty.kernel_name() == name {
+                return Some(ty);
+            }
+            i += 1;
+        }
+
+        None
+    }
+}
+
+/// Tasks walked by a task iterator, see [`Program::attach_iter_task`].
+#[derive(Clone, Debug)]
+pub enum TaskIterTarget {
+    /// Only the thread with this thread id.
+    Thread(u32),
+    /// All threads of the process with this pid.
+    Process(u32),
+    /// All threads of the process referred to by this pidfd.
+    PidFd(i32),
+}
+
+/// Results of [`Program::can_use_helper`], keyed by (program type, helper id).
+static HELPER_PROBES: Mutex<Option<HashMap<(u32, u32), bool>>> = Mutex::new(None);
+
+bitflags! {
+    /// Flags to control how a program is attached to a cgroup. Maps to the `BPF_F_*` attach
+    /// flags in kernel uapi.
+    ///
+    /// `ALLOW_OVERRIDE` and `ALLOW_MULTI` only apply to programs attached without a link, with
+    /// [`Program::attach_cgroup_legacy`]. Cgroup links, see [`Program::attach_cgroup_opts`],
+    /// always attach as if with `ALLOW_MULTI` and reject both. `BEFORE`, `AFTER` and
+    /// `PREORDER` require Linux 6.15 or later.
+    pub struct CgroupAttachFlags: u32 {
+        /// Let programs attached to descendant cgroups override this one.
+        const ALLOW_OVERRIDE = 1 << 0;
+        /// Let several programs attach to the hook, all of them run. Programs attached
+        /// to descendant cgroups run too.
+        const ALLOW_MULTI    = 1 << 1;
+        /// Run before the programs already attached to the hook.
+        const BEFORE         = 1 << 3;
+        /// Run after the programs already attached to the hook.
+        const AFTER          = 1 << 4;
+        /// Run before the programs attached to ancestor cgroups, instead of after.
+        const PREORDER       = 1 << 6;
+    }
+}
+
+/// Options to control how a kprobe is attached. See [`Program::attach_kprobe_opts`].
+#[derive(Clone, Debug, Default)]
+pub struct KprobeOpts {
+    /// Force the legacy attach path, which creates the probe through the tracefs
+    /// `kprobe_events` interface instead of a perf-based `kprobe` PMU event. Leave this unset
+    /// (the default) to let libbpf auto-detect what the running kernel supports. Set it on
+    /// kernels whose perf-kprobe support is missing or known to misbehave.
+    pub legacy: bool,
+    /// Offset into the function to probe, e.g. an instruction boundary found through
+    /// disassembly. 0 probes the function entry.
+    pub offset: usize,
+    /// Value returned by `bpf_get_attach_cookie()` in the program.
+    pub cookie: u64,
+}
+
+/// Options to control how a uprobe is attached. See [`Program::attach_uprobe_opts`].
+#[derive(Clone, Debug, Default)]
+pub struct UprobeOpts {
+    /// Name of the function to attach to. If set, libbpf looks up the symbol in the binary and
+    /// `func_offset` is taken relative to it.
+    pub func_name: Option<String>,
+    /// Value returned by `bpf_get_attach_cookie()` in the program, e.g. to tell apart multiple
+    /// uprobes sharing one program.
+    pub cookie: u64,
+    /// Offset of the reference counter of a USDT semaphore guarding the probe, 0 if none.
+    pub ref_ctr_offset: usize,
+}
+
+/// Context of a program taking `struct __sk_buff`, e.g. a [`ProgramType::SchedCls`]
+/// classifier, for [`Program::prog_run_ctx`].
+///
+/// Only the fields the kernel accepts as test run input are exposed, all others are zero.
+/// Use [`SkBuffContext::to_bytes`] to build `ctx_in` and [`SkBuffContext::from_bytes`] to
+/// read back `ctx_out`, which holds the values after the program ran.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SkBuffContext {
+    pub mark: u32,
+    pub priority: u32,
+    pub ingress_ifindex: u32,
+    /// Must be 0, 1 (loopback) or refer to an existing interface.
+    pub ifindex: u32,
+    pub cb: [u32; 5],
+    pub tstamp: u64,
+    /// Length of the packet on the wire, 0 to use the length of `data_in`.
+    pub wire_len: u32,
+    pub gso_segs: u32,
+    pub gso_size: u32,
+    pub hwtstamp: u64,
+}
+
+impl SkBuffContext {
+    /// `sizeof(struct __sk_buff)`
+    pub const SIZE: usize = 192;
+
+    // Offsets of the fields in `struct __sk_buff`
+    const MARK: usize = 8;
+    const PRIORITY: usize = 32;
+    const INGRESS_IFINDEX: usize = 36;
+    const IFINDEX: usize = 40;
+    const CB: usize = 48;
+    const TSTAMP: usize = 152;
+    const WIRE_LEN: usize = 160;
+    const GSO_SEGS: usize = 164;
+    const GSO_SIZE: usize = 176;
+    const HWTSTAMP: usize = 184;
+
+    /// Serializes the context to the layout of `struct __sk_buff`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0; Self::SIZE];
+        let mut put = |off: usize, bytes: &[u8]| buf[off..off + bytes.len()].copy_from_slice(bytes);
+
+        put(Self::MARK, &self.mark.to_ne_bytes());
+        put(Self::PRIORITY, &self.priority.to_ne_bytes());
+        put(Self::INGRESS_IFINDEX, &self.ingress_ifindex.to_ne_bytes());
+        put(Self::IFINDEX, &self.ifindex.to_ne_bytes());
+        for (i, cb) in self.cb.iter().enumerate() {
+            put(Self::CB + i * 4, &cb.to_ne_bytes());
+        }
+        put(Self::TSTAMP, &self.tstamp.to_ne_bytes());
+        put(Self::WIRE_LEN, &self.wire_len.to_ne_bytes());
+        put(Self::GSO_SEGS, &self.gso_segs.to_ne_bytes());
+        put(Self::GSO_SIZE, &self.gso_size.to_ne_bytes());
+        put(Self::HWTSTAMP, &self.hwtstamp.to_ne_bytes());
+
+        buf
+    }
+
+    /// Parses a `struct __sk_buff`, e.g. the `ctx_out` of a test run. `bytes` must be
+    /// exactly [`SkBuffContext::SIZE`] bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::SIZE {
+            return Err(Error::InvalidInput(format!(
+                "__sk_buff is {} bytes, got {}",
+                Self::SIZE,
+                bytes.len()
+            )));
+        }
+
+        let u32_at = |off: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&bytes[off..off + 4]);
+            u32::from_ne_bytes(b)
+        };
+        let u64_at = |off: usize| {
+            let mut b = [0; 8];
+            b.copy_from_slice(&bytes[off..off + 8]);
+            u64::from_ne_bytes(b)
+        };
+
+        let mut cb = [0; 5];
+        for (i, v) in cb.iter_mut().enumerate() {
+            *v = u32_at(Self::CB + i * 4);
+        }
+
+        Ok(SkBuffContext {
+            mark: u32_at(Self::MARK),
+            priority: u32_at(Self::PRIORITY),
+            ingress_ifindex: u32_at(Self::INGRESS_IFINDEX),
+            ifindex: u32_at(Self::IFINDEX),
+            cb,
+            tstamp: u64_at(Self::TSTAMP),
+            wire_len: u32_at(Self::WIRE_LEN),
+            gso_segs: u32_at(Self::GSO_SEGS),
+            gso_size: u32_at(Self::GSO_SIZE),
+            hwtstamp: u64_at(Self::HWTSTAMP),
+        })
+    }
+}
+
 /// Represents a loaded [`Program`].
 ///
 /// This struct is not safe to clone because the underlying libbpf resource cannot currently
@@ -136,15 +556,55 @@ pub enum ProgramAttachType {
 ///
 /// If you attempt to attach a `Program` with the wrong attach method, the `attach_*`
 /// method will fail with the appropriate error.
+///
+/// A `Program` created with [`create_program_from_insns`] does not belong to an [`Object`]
+/// and only owns its file descriptor. The `attach_*` methods that go through libbpf fail with
+/// [`Error::InvalidInput`] for such programs.
 pub struct Program {
     pub(crate) ptr: *mut libbpf_sys::bpf_program,
+    fd: i32,
     name: String,
     section: String,
+    log: Option<String>,
+    pin_path: Option<PathBuf>,
 }
 
 impl Program {
-    pub(crate) fn new(ptr: *mut libbpf_sys::bpf_program, name: String, section: String) -> Self {
-        Program { ptr, name, section }
+    pub(crate) fn new(
+        ptr: *mut libbpf_sys::bpf_program,
+        name: String,
+        section: String,
+        log: Option<String>,
+    ) -> Self {
+        Program {
+            ptr,
+            fd: unsafe { libbpf_sys::bpf_program__fd(ptr) },
+            name,
+            section,
+            log,
+            pin_path: None,
+        }
+    }
+
+    /// Returns the libbpf program backing this `Program`, if it was loaded from an [`Object`].
+    pub(crate) fn libbpf_prog(&self) -> Result<*mut libbpf_sys::bpf_program> {
+        if self.ptr.is_null() {
+            Err(Error::InvalidInput(
+                "program was not loaded from an Object".to_string(),
+            ))
+        } else {
+            Ok(self.ptr)
+        }
+    }
+
+    fn raw_prog_type(&self) -> libbpf_sys::bpf_prog_type {
+        if self.ptr.is_null() {
+            wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd)
+                .map(|info| info.type_)
+                .unwrap_or(libbpf_sys::BPF_PROG_TYPE_UNSPEC)
+        } else {
+            unsafe { libbpf_sys::bpf_program__type(self.ptr) }
+        }
     }
 
     pub fn name(&self) -> &str {
@@ -156,21 +616,184 @@ impl Program {
         &self.section
     }
 
+    /// Verifier log captured while loading this `Program`, if a log level was set with
+    /// [`OpenProgram::set_log_level`]. Only available when loaded through [`OpenObject::load`].
+    /// The log is also kept when loading succeeds, e.g. to audit the verifier's work.
+    pub fn verifier_log(&self) -> Option<&str> {
+        self.log.as_deref()
+    }
+
     pub fn prog_type(&self) -> ProgramType {
-        match ProgramType::try_from(unsafe { libbpf_sys::bpf_program__get_type(self.ptr) }) {
+        match ProgramType::try_from(self.raw_prog_type()) {
             Ok(ty) => ty,
             Err(_) => ProgramType::Unknown,
         }
     }
 
+    /// Returns whether BPF helper `helper_id` (see `enum bpf_func_id` in kernel uapi) can be
+    /// called by programs of this program's type on the running kernel.
+    ///
+    /// Probing loads a small test program, so results are cached per (type, helper). libbpf
+    /// cannot probe helpers for program types that need an attach target (e.g.
+    /// [`ProgramType::Tracing`] or [`ProgramType::Lsm`]), those fail with `EOPNOTSUPP`.
+    pub fn can_use_helper(&self, helper_id: u32) -> Result<bool> {
+        let prog_type = self.raw_prog_type();
+        let key = (prog_type, helper_id);
+
+        if let Some(supported) = HELPER_PROBES
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|probes| probes.get(&key))
+        {
+            return Ok(*supported);
+        }
+
+        let supported = wrappers::probe_helper(prog_type, helper_id)?;
+        HELPER_PROBES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(key, supported);
+
+        Ok(supported)
+    }
+
     /// Returns a file descriptor to the underlying program.
     pub fn fd(&self) -> i32 {
-        unsafe { libbpf_sys::bpf_program__fd(self.ptr) }
+        self.fd
+    }
+
+    /// Returns the instructions of this program.
+    ///
+    /// For programs loaded from an [`Object`] these are the instructions libbpf passed to the
+    /// kernel, after relocation, so they can be loaded again with [`create_program_from_insns`]
+    /// while the `Object` is alive. For other programs the kernel's translated instructions are
+    /// returned.
+    pub fn dump_insns(&self) -> Result<Vec<libbpf_sys::bpf_insn>> {
+        if !self.ptr.is_null() {
+            let insns = unsafe { libbpf_sys::bpf_program__insns(self.ptr) };
+            let cnt = unsafe { libbpf_sys::bpf_program__insn_cnt(self.ptr) } as usize;
+            if insns.is_null() {
+                return Ok(Vec::new());
+            }
+            return Ok(unsafe { std::slice::from_raw_parts(insns, cnt) }.to_vec());
+        }
+
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd)?;
+        let cnt = info.xlated_prog_len as usize / mem::size_of::<libbpf_sys::bpf_insn>();
+        let mut insns = vec![libbpf_sys::bpf_insn::default(); cnt];
+
+        let mut info: libbpf_sys::bpf_prog_info = unsafe { mem::zeroed() };
+        info.xlated_prog_len = (cnt * mem::size_of::<libbpf_sys::bpf_insn>()) as u32;
+        info.xlated_prog_insns = insns.as_mut_ptr() as u64;
+        let mut len = mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+        let ret = unsafe { libbpf_sys::bpf_prog_get_info_by_fd(self.fd, &mut info, &mut len) };
+        if ret != 0 {
+            return Err(Error::System(-ret));
+        }
+
+        // The program cannot change once loaded, but stay within what the kernel wrote.
+        let written = info.xlated_prog_len as usize / mem::size_of::<libbpf_sys::bpf_insn>();
+        insns.truncate(written);
+        Ok(insns)
+    }
+
+    /// Returns the number of instructions of this program, see [`Program::dump_insns()`].
+    ///
+    /// For programs loaded from an [`Object`] this is the count libbpf passed to the kernel. For
+    /// other programs it is derived from the kernel's translated instructions, and 0 if the
+    /// kernel does not report them. [`query::ProgramInfo::verified_insns`] has the number of
+    /// instructions the verifier processed.
+    pub fn insn_cnt(&self) -> usize {
+        if !self.ptr.is_null() {
+            return unsafe { libbpf_sys::bpf_program__insn_cnt(self.ptr) } as usize;
+        }
+
+        match wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd) {
+            Ok(info) => info.xlated_prog_len as usize / mem::size_of::<libbpf_sys::bpf_insn>(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns whether this program was loaded as sleepable (with `BPF_F_SLEEPABLE`), which
+    /// libbpf does for programs in sleepable sections such as `uprobe.s` or `lsm.s`. Always
+    /// `false` for programs that were not loaded from an [`Object`].
+    pub fn is_sleepable(&self) -> bool {
+        !self.ptr.is_null()
+            && unsafe { libbpf_sys::bpf_program__flags(self.ptr) } & libbpf_sys::BPF_F_SLEEPABLE
+                != 0
+    }
+
+    /// Returns the memory in bytes the kernel charges for this program, as shown by
+    /// `bpftool prog show`. Read from the `memlock` field of the program's fdinfo.
+    pub fn memlock_bytes(&self) -> Result<u64> {
+        util::fdinfo_field(self.fd, "memlock")
+    }
+
+    /// Opens the loaded program with the given id, e.g. one found through
+    /// [`query::ProgInfoIter`] or loaded by another process.
+    ///
+    /// The name is the one the kernel knows, which is truncated to 15 characters. The section
+    /// is not known to the kernel and left empty. Like programs created with
+    /// [`create_program_from_insns`], the returned `Program` owns its file descriptor.
+    pub fn from_id(id: u32) -> Result<Program> {
+        let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(id) };
+        if fd < 0 {
+            return Err(Error::System(errno::errno()));
+        }
+
+        let info = match wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(fd) {
+            Ok(info) => info,
+            Err(e) => {
+                let _ = unistd::close(fd);
+                return Err(e);
+            }
+        };
+
+        Ok(Program {
+            ptr: ptr::null_mut(),
+            fd,
+            name: query::name_arr_to_string(&info.name, ""),
+            section: String::new(),
+            log: None,
+            pin_path: None,
+        })
+    }
+
+    /// Returns the id the kernel assigned to this program.
+    pub fn id(&self) -> Result<u32> {
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd)?;
+        Ok(info.id)
+    }
+
+    /// Returns what the kernel reports about this program, such as its id, name, instruction
+    /// counts and, if run statistics are enabled with the `kernel.bpf_stats_enabled` sysctl,
+    /// how often it ran.
+    pub fn info(&self) -> Result<query::ProgramInfo> {
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd)?;
+        query::ProgramInfo::from_uapi(self.fd, info)
+            .ok_or_else(|| Error::Internal("failed to parse program info".to_string()))
+    }
+
+    /// Returns whether the kernel JIT compiled this program.
+    ///
+    /// Programs that are not JIT compiled are interpreted, which is much slower. That is the
+    /// case when the JIT is disabled with the `net.core.bpf_jit_enable` sysctl. The kernel
+    /// hides JIT details from users that may not dump raw instructions (see the
+    /// `kernel.kptr_restrict` sysctl), for them this always returns `false`.
+    pub fn is_jited(&self) -> Result<bool> {
+        let info = wrappers::bpf_obj_get_info_by_fd::<libbpf_sys::bpf_prog_info>(self.fd)?;
+        Ok(info.jited_prog_len > 0)
     }
 
     pub fn attach_type(&self) -> ProgramAttachType {
+        if self.ptr.is_null() {
+            return ProgramAttachType::Unknown;
+        }
+
         match ProgramAttachType::try_from(unsafe {
-            libbpf_sys::bpf_program__get_expected_attach_type(self.ptr)
+            libbpf_sys::bpf_program__expected_attach_type(self.ptr)
         }) {
             Ok(ty) => ty,
             Err(_) => ProgramAttachType::Unknown,
@@ -180,36 +803,77 @@ impl Program {
     /// [Pin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// this program to bpffs.
     pub fn pin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let path_c = util::path_to_cstring(path)?;
+        let path_c = util::path_to_cstring(&path)?;
         let path_ptr = path_c.as_ptr();
 
-        let ret = unsafe { libbpf_sys::bpf_program__pin(self.ptr, path_ptr) };
+        let ret = if self.ptr.is_null() {
+            unsafe { libbpf_sys::bpf_obj_pin(self.fd, path_ptr) }
+        } else {
+            unsafe { libbpf_sys::bpf_program__pin(self.ptr, path_ptr) }
+        };
         if ret != 0 {
             // Error code is returned negative, flip to positive to match errno
             Err(Error::System(-ret))
         } else {
+            self.pin_path = Some(path.as_ref().to_path_buf());
             Ok(())
         }
     }
 
+    /// Returns whether this program was pinned with [`Program::pin()`] and not unpinned since.
+    pub fn is_pinned(&self) -> bool {
+        self.pin_path.is_some()
+    }
+
+    /// Returns the path this program was last pinned to with [`Program::pin()`], if it has not
+    /// been unpinned from there since.
+    ///
+    /// libbpf does not track program pins, so pins made by other means, e.g. by an earlier run
+    /// of the same daemon, are not known. Check whether the path exists in bpffs for those.
+    pub fn pin_path(&self) -> Option<&Path> {
+        self.pin_path.as_deref()
+    }
+
+    /// Same as [`Program::pin()`], but also sets the permissions of the pinned file to `mode` (e.g.
+    /// `0o640`). If that fails, the program is unpinned again.
+    ///
+    /// Note that other users also need search permission on every parent directory of `path`
+    /// to access the pinned program.
+    pub fn pin_with_mode<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> Result<()> {
+        self.pin(&path)?;
+        if let Err(e) = util::set_mode(&path, mode) {
+            let _ = self.unpin(&path);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
     /// [Unpin](https://facebookmicrosites.github.io/bpf/blog/2018/08/31/object-lifetime.html#bpffs)
     /// this program from bpffs
     pub fn unpin<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let path_c = util::path_to_cstring(path)?;
-        let path_ptr = path_c.as_ptr();
-
-        let ret = unsafe { libbpf_sys::bpf_program__unpin(self.ptr, path_ptr) };
-        if ret != 0 {
-            // Error code is returned negative, flip to positive to match errno
-            Err(Error::System(-ret))
+        if self.ptr.is_null() {
+            std::fs::remove_file(&path).map_err(util::io_error_to_error)?;
         } else {
-            Ok(())
+            let path_c = util::path_to_cstring(&path)?;
+            let path_ptr = path_c.as_ptr();
+
+            let ret = unsafe { libbpf_sys::bpf_program__unpin(self.ptr, path_ptr) };
+            if ret != 0 {
+                // Error code is returned negative, flip to positive to match errno
+                return Err(Error::System(-ret));
+            }
+        }
+
+        if self.pin_path.as_deref() == Some(path.as_ref()) {
+            self.pin_path = None;
         }
+        Ok(())
     }
 
     /// Auto-attach based on prog section
-    pub fn attach(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach(self.ptr) };
+    pub fn attach(&self) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach(self.libbpf_prog()?) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -220,8 +884,101 @@ impl Program {
 
     /// Attach this program to a
     /// [cgroup](https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html).
-    pub fn attach_cgroup(&mut self, cgroup_fd: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_cgroup(self.ptr, cgroup_fd) };
+    pub fn attach_cgroup(&self, cgroup_fd: i32) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_cgroup(self.libbpf_prog()?, cgroup_fd) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this program to a
+    /// [cgroup](https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html), with
+    /// `flags` controlling where it runs relative to other programs on the same hook.
+    ///
+    /// Like with [`Program::attach_cgroup`], other tools can attach their own links, or
+    /// programs attached with [`CgroupAttachFlags::ALLOW_MULTI`], to the same hook. Hooks
+    /// that have a program attached without `ALLOW_MULTI` reject the link with `EPERM`.
+    /// `ALLOW_MULTI` and `ALLOW_OVERRIDE` are rejected with [`Error::InvalidInput`], use
+    /// [`Program::attach_cgroup_legacy`] for those.
+    pub fn attach_cgroup_opts(&self, cgroup_fd: i32, flags: CgroupAttachFlags) -> Result<Link> {
+        if flags.intersects(CgroupAttachFlags::ALLOW_MULTI | CgroupAttachFlags::ALLOW_OVERRIDE) {
+            return Err(Error::InvalidInput(
+                "cgroup links don't take ALLOW_MULTI or ALLOW_OVERRIDE".to_string(),
+            ));
+        }
+
+        let opts = libbpf_sys::bpf_cgroup_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_cgroup_opts>() as libbpf_sys::size_t,
+            flags: flags.bits(),
+            ..Default::default()
+        };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_cgroup_opts(self.libbpf_prog()?, cgroup_fd, &opts)
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this program to a
+    /// [cgroup](https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html) without a
+    /// link, with `BPF_PROG_ATTACH`.
+    ///
+    /// Use [`CgroupAttachFlags::ALLOW_MULTI`] to share the hook with programs from other
+    /// tools, and [`CgroupAttachFlags::ALLOW_OVERRIDE`] to let descendant cgroups replace this
+    /// program. Without `ALLOW_MULTI`, attaching again replaces the program attached to the
+    /// hook. All programs attached without a link to one hook must use the same flags, the
+    /// kernel rejects others with `EPERM`.
+    ///
+    /// The program stays attached until [`Program::detach_cgroup_legacy`] is called or the
+    /// cgroup is removed, there is no [`Link`].
+    pub fn attach_cgroup_legacy(&self, cgroup_fd: i32, flags: CgroupAttachFlags) -> Result<()> {
+        let attach_type = self.cgroup_attach_type()?;
+        let ret =
+            unsafe { libbpf_sys::bpf_prog_attach(self.fd(), cgroup_fd, attach_type, flags.bits()) };
+        if ret != 0 {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(())
+    }
+
+    /// Detach this program from a cgroup it was attached to with
+    /// [`Program::attach_cgroup_legacy`].
+    pub fn detach_cgroup_legacy(&self, cgroup_fd: i32) -> Result<()> {
+        let attach_type = self.cgroup_attach_type()?;
+        let ret = unsafe { libbpf_sys::bpf_prog_detach2(self.fd(), cgroup_fd, attach_type) };
+        if ret != 0 {
+            return Err(Error::System(errno::errno()));
+        }
+
+        Ok(())
+    }
+
+    /// The hook a cgroup program attaches to is its expected attach type, which libbpf takes
+    /// from the section name.
+    fn cgroup_attach_type(&self) -> Result<u32> {
+        match self.attach_type() {
+            ProgramAttachType::Unknown => Err(Error::InvalidInput(format!(
+                "program {} has no attach type",
+                self.name()
+            ))),
+            ty => Ok(ty as u32),
+        }
+    }
+
+    /// Attach this program to a [network
+    /// namespace](https://man7.org/linux/man-pages/man7/network_namespaces.7.html), as done
+    /// for [`ProgramType::FlowDissector`] and `sk_lookup` programs. `netns_fd` is an fd of the
+    /// namespace, e.g. from opening `/proc/<pid>/ns/net`.
+    pub fn attach_netns(&self, netns_fd: i32) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_netns(self.libbpf_prog()?, netns_fd) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -231,8 +988,30 @@ impl Program {
     }
 
     /// Attach this program to a [perf event](https://linux.die.net/man/2/perf_event_open).
-    pub fn attach_perf_event(&mut self, pfd: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_perf_event(self.ptr, pfd) };
+    pub fn attach_perf_event(&self, pfd: i32) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_perf_event(self.libbpf_prog()?, pfd) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this program to a [perf event](https://linux.die.net/man/2/perf_event_open), with
+    /// a `cookie` that the program can read with `bpf_get_attach_cookie()`.
+    ///
+    /// On success the returned [`Link`] takes ownership of `pfd` and closes it when dropped, see
+    /// [`PerfEvent::into_fd()`].
+    pub fn attach_perf_event_opts(&self, pfd: i32, cookie: u64) -> Result<Link> {
+        let opts = libbpf_sys::bpf_perf_event_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_perf_event_opts>() as libbpf_sys::size_t,
+            bpf_cookie: cookie,
+            ..Default::default()
+        };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_perf_event_opts(self.libbpf_prog()?, pfd, &opts)
+        };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -244,7 +1023,7 @@ impl Program {
     /// Attach this program to a [userspace
     /// probe](https://www.kernel.org/doc/html/latest/trace/uprobetracer.html).
     pub fn attach_uprobe<T: AsRef<Path>>(
-        &mut self,
+        &self,
         retprobe: bool,
         pid: i32,
         binary_path: T,
@@ -254,7 +1033,7 @@ impl Program {
         let path_ptr = path.as_ptr();
         let ptr = unsafe {
             libbpf_sys::bpf_program__attach_uprobe(
-                self.ptr,
+                self.libbpf_prog()?,
                 retprobe,
                 pid,
                 path_ptr,
@@ -269,13 +1048,200 @@ impl Program {
         }
     }
 
+    /// Attach this program to a [userspace
+    /// probe](https://www.kernel.org/doc/html/latest/trace/uprobetracer.html), with the symbol
+    /// name, cookie and reference counter given in `opts`.
+    pub fn attach_uprobe_opts<T: AsRef<Path>>(
+        &self,
+        retprobe: bool,
+        pid: i32,
+        binary_path: T,
+        func_offset: usize,
+        opts: UprobeOpts,
+    ) -> Result<Link> {
+        let path = util::path_to_cstring(binary_path.as_ref())?;
+        let path_ptr = path.as_ptr();
+        let func_name = match opts.func_name {
+            Some(name) => Some(util::str_to_cstring(&name)?),
+            None => None,
+        };
+        let func_name_ptr = func_name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+        let opts = libbpf_sys::bpf_uprobe_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_uprobe_opts>() as libbpf_sys::size_t,
+            ref_ctr_offset: opts.ref_ctr_offset as libbpf_sys::size_t,
+            bpf_cookie: opts.cookie,
+            retprobe,
+            func_name: func_name_ptr,
+            ..Default::default()
+        };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_uprobe_opts(
+                self.libbpf_prog()?,
+                pid,
+                path_ptr,
+                func_offset as libbpf_sys::size_t,
+                &opts,
+            )
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Same as [`Program::attach_uprobe()`], but checks that this program is sleepable first.
+    ///
+    /// Sleepable uprobe programs may fault in userspace memory, so reads with
+    /// `bpf_copy_from_user()` of e.g. strings of the traced process do not fail just because
+    /// the memory is paged out. Programs only become sleepable when placed in a `uprobe.s` or
+    /// `uretprobe.s` section, others fail with [`Error::InvalidInput`].
+    pub fn attach_uprobe_sleepable<T: AsRef<Path>>(
+        &self,
+        retprobe: bool,
+        pid: i32,
+        binary_path: T,
+        func_offset: usize,
+    ) -> Result<Link> {
+        if !self.is_sleepable() {
+            return Err(Error::InvalidInput(format!(
+                "program {} is not sleepable, it must be in a uprobe.s or uretprobe.s section",
+                self.name()
+            )));
+        }
+
+        self.attach_uprobe(retprobe, pid, binary_path, func_offset)
+    }
+
+    /// Attach this program to a [userspace statically defined
+    /// tracepoint](https://docs.kernel.org/trace/uprobetracer.html) (USDT) named `usdt_name` of
+    /// `usdt_provider` in `binary_path`.
+    ///
+    /// libbpf locates the probe and its semaphore, if any, from the binary's ELF notes. `cookie`
+    /// is returned by `bpf_usdt_cookie()` in the program.
+    pub fn attach_usdt<T: AsRef<Path>>(
+        &self,
+        pid: i32,
+        binary_path: T,
+        usdt_provider: &str,
+        usdt_name: &str,
+        cookie: u64,
+    ) -> Result<Link> {
+        let path = util::path_to_cstring(binary_path.as_ref())?;
+        let path_ptr = path.as_ptr();
+        let usdt_provider = util::str_to_cstring(usdt_provider)?;
+        let usdt_provider_ptr = usdt_provider.as_ptr();
+        let usdt_name = util::str_to_cstring(usdt_name)?;
+        let usdt_name_ptr = usdt_name.as_ptr();
+        let opts = libbpf_sys::bpf_usdt_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_usdt_opts>() as libbpf_sys::size_t,
+            usdt_cookie: cookie,
+        };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_usdt(
+                self.libbpf_prog()?,
+                pid,
+                path_ptr,
+                usdt_provider_ptr,
+                usdt_name_ptr,
+                &opts,
+            )
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
     /// Attach this program to a [kernel
     /// probe](https://www.kernel.org/doc/html/latest/trace/kprobetrace.html).
-    pub fn attach_kprobe<T: AsRef<str>>(&mut self, retprobe: bool, func_name: T) -> Result<Link> {
+    pub fn attach_kprobe<T: AsRef<str>>(&self, retprobe: bool, func_name: T) -> Result<Link> {
+        self.attach_kprobe_opts(retprobe, func_name, KprobeOpts::default())
+    }
+
+    /// Attach this program to all kernel functions matching the glob `pattern`, e.g. `vfs_*`,
+    /// with a single [multi kprobe](https://docs.kernel.org/trace/fprobe.html) link.
+    ///
+    /// The program must be of the `kprobe.multi` or `kretprobe.multi` section type. Requires a
+    /// kernel of version 5.18 or later.
+    pub fn attach_kprobe_multi(&self, retprobe: bool, pattern: &str) -> Result<Link> {
+        let pattern = util::str_to_cstring(pattern)?;
+        let opts = libbpf_sys::bpf_kprobe_multi_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_kprobe_multi_opts>() as libbpf_sys::size_t,
+            retprobe,
+            ..Default::default()
+        };
+        self.attach_kprobe_multi_raw(pattern.as_ptr(), &opts)
+    }
+
+    /// Attach this program to the kernel functions `syms` with a single multi kprobe link, see
+    /// [`Program::attach_kprobe_multi()`].
+    pub fn attach_kprobe_multi_syms<T: AsRef<str>>(
+        &self,
+        retprobe: bool,
+        syms: &[T],
+    ) -> Result<Link> {
+        let syms = syms
+            .iter()
+            .map(|sym| util::str_to_cstring(sym.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut sym_ptrs = syms.iter().map(|sym| sym.as_ptr()).collect::<Vec<_>>();
+        let opts = libbpf_sys::bpf_kprobe_multi_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_kprobe_multi_opts>() as libbpf_sys::size_t,
+            syms: sym_ptrs.as_mut_ptr(),
+            cnt: sym_ptrs.len() as libbpf_sys::size_t,
+            retprobe,
+            ..Default::default()
+        };
+        self.attach_kprobe_multi_raw(ptr::null(), &opts)
+    }
+
+    fn attach_kprobe_multi_raw(
+        &self,
+        pattern: *const c_char,
+        opts: &libbpf_sys::bpf_kprobe_multi_opts,
+    ) -> Result<Link> {
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_kprobe_multi_opts(self.libbpf_prog()?, pattern, opts)
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this program to a [kernel
+    /// probe](https://www.kernel.org/doc/html/latest/trace/kprobetrace.html), using the attach
+    /// mode, offset and cookie selected in `opts`.
+    pub fn attach_kprobe_opts<T: AsRef<str>>(
+        &self,
+        retprobe: bool,
+        func_name: T,
+        opts: KprobeOpts,
+    ) -> Result<Link> {
         let func_name = util::str_to_cstring(func_name.as_ref())?;
         let func_name_ptr = func_name.as_ptr();
-        let ptr =
-            unsafe { libbpf_sys::bpf_program__attach_kprobe(self.ptr, retprobe, func_name_ptr) };
+        let attach_mode = if opts.legacy {
+            libbpf_sys::PROBE_ATTACH_MODE_LEGACY
+        } else {
+            libbpf_sys::PROBE_ATTACH_MODE_DEFAULT
+        };
+        let opts = libbpf_sys::bpf_kprobe_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_kprobe_opts>() as libbpf_sys::size_t,
+            bpf_cookie: opts.cookie,
+            offset: opts.offset as libbpf_sys::size_t,
+            retprobe,
+            attach_mode,
+            ..Default::default()
+        };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_kprobe_opts(self.libbpf_prog()?, func_name_ptr, &opts)
+        };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -286,13 +1252,51 @@ impl Program {
 
     /// Attach this program to a [kernel
     /// tracepoint](https://www.kernel.org/doc/html/latest/trace/tracepoints.html).
-    pub fn attach_tracepoint<T: AsRef<str>>(&mut self, tp_category: T, tp_name: T) -> Result<Link> {
+    pub fn attach_tracepoint<T: AsRef<str>>(&self, tp_category: T, tp_name: T) -> Result<Link> {
+        let tp_category = util::str_to_cstring(tp_category.as_ref())?;
+        let tp_category_ptr = tp_category.as_ptr();
+        let tp_name = util::str_to_cstring(tp_name.as_ref())?;
+        let tp_name_ptr = tp_name.as_ptr();
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_tracepoint(
+                self.libbpf_prog()?,
+                tp_category_ptr,
+                tp_name_ptr,
+            )
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this program to a [kernel
+    /// tracepoint](https://www.kernel.org/doc/html/latest/trace/tracepoints.html), with a
+    /// `cookie` that the program can read with `bpf_get_attach_cookie()`, e.g. to tell apart
+    /// the tracepoints a shared handler is attached to.
+    pub fn attach_tracepoint_opts<T: AsRef<str>>(
+        &self,
+        tp_category: T,
+        tp_name: T,
+        cookie: u64,
+    ) -> Result<Link> {
         let tp_category = util::str_to_cstring(tp_category.as_ref())?;
         let tp_category_ptr = tp_category.as_ptr();
         let tp_name = util::str_to_cstring(tp_name.as_ref())?;
         let tp_name_ptr = tp_name.as_ptr();
+        let opts = libbpf_sys::bpf_tracepoint_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_tracepoint_opts>() as libbpf_sys::size_t,
+            bpf_cookie: cookie,
+        };
         let ptr = unsafe {
-            libbpf_sys::bpf_program__attach_tracepoint(self.ptr, tp_category_ptr, tp_name_ptr)
+            libbpf_sys::bpf_program__attach_tracepoint_opts(
+                self.libbpf_prog()?,
+                tp_category_ptr,
+                tp_name_ptr,
+                &opts,
+            )
         };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
@@ -304,10 +1308,12 @@ impl Program {
 
     /// Attach this program to a [raw kernel
     /// tracepoint](https://lwn.net/Articles/748352/).
-    pub fn attach_raw_tracepoint<T: AsRef<str>>(&mut self, tp_name: T) -> Result<Link> {
+    pub fn attach_raw_tracepoint<T: AsRef<str>>(&self, tp_name: T) -> Result<Link> {
         let tp_name = util::str_to_cstring(tp_name.as_ref())?;
         let tp_name_ptr = tp_name.as_ptr();
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_raw_tracepoint(self.ptr, tp_name_ptr) };
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_raw_tracepoint(self.libbpf_prog()?, tp_name_ptr)
+        };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -317,8 +1323,8 @@ impl Program {
     }
 
     /// Attach to an [LSM](https://en.wikipedia.org/wiki/Linux_Security_Modules) hook
-    pub fn attach_lsm(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_lsm(self.ptr) };
+    pub fn attach_lsm(&self) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_lsm(self.libbpf_prog()?) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -328,8 +1334,8 @@ impl Program {
     }
 
     /// Attach to a [fentry/fexit kernel probe](https://lwn.net/Articles/801479/)
-    pub fn attach_trace(&mut self) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_trace(self.ptr) };
+    pub fn attach_trace(&self) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_trace(self.libbpf_prog()?) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -349,9 +1355,137 @@ impl Program {
         }
     }
 
+    /// Attach this [`ProgramType::SocketFilter`] program to the socket `socket_fd` with
+    /// `setsockopt(SO_ATTACH_BPF)`.
+    ///
+    /// The program stays attached until the socket is closed, there is no [`Link`].
+    pub fn attach_socket(&self, socket_fd: i32) -> Result<()> {
+        let prog_type = self.prog_type();
+        if !matches!(prog_type, ProgramType::SocketFilter) {
+            return Err(Error::InvalidInput(format!(
+                "cannot attach {} program {} to a socket, expected SocketFilter",
+                prog_type,
+                self.name()
+            )));
+        }
+
+        let prog_fd = self.fd();
+        let ret = unsafe {
+            libc::setsockopt(
+                socket_fd,
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_BPF,
+                &prog_fd as *const i32 as *const c_void,
+                mem::size_of::<i32>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            Err(Error::System(errno::errno()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Attach this [iterator](https://docs.kernel.org/bpf/bpf_iterators.html) program, i.e.
+    /// one in an `iter/` section.
+    ///
+    /// Map element iterators like `iter/bpf_map_elem` need the fd of the map to walk in
+    /// `map_fd`, other iterators take `None`. Read the output by creating an [`Iter`] from the
+    /// returned [`Link`].
+    pub fn attach_iter(&self, map_fd: Option<i32>) -> Result<Link> {
+        let prog = self.libbpf_prog()?;
+        let attach_type = unsafe { libbpf_sys::bpf_program__expected_attach_type(prog) };
+        if attach_type != libbpf_sys::BPF_TRACE_ITER {
+            return Err(Error::InvalidInput(format!(
+                "program {} in section {} is not an iterator",
+                self.name, self.section
+            )));
+        }
+
+        let mut link_info = libbpf_sys::bpf_iter_link_info::default();
+        let mut opts = libbpf_sys::bpf_iter_attach_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_iter_attach_opts>() as libbpf_sys::size_t,
+            ..Default::default()
+        };
+        if let Some(map_fd) = map_fd {
+            link_info.map.map_fd = map_fd as u32;
+            opts.link_info = &mut link_info;
+            opts.link_info_len = mem::size_of::<libbpf_sys::bpf_iter_link_info>() as u32;
+        }
+
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_iter(prog, &opts) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this task [iterator](https://docs.kernel.org/bpf/bpf_iterators.html) program,
+    /// i.e. one in an `iter/task*` section, so that it only walks the tasks in `target`.
+    ///
+    /// Read the output by creating an [`Iter`] from the returned [`Link`]. Task iterators
+    /// attached with [`Program::attach()`] walk all tasks instead. Task targets need Linux
+    /// 6.1 or newer, older kernels fail with `EINVAL`.
+    pub fn attach_iter_task(&self, target: TaskIterTarget) -> Result<Link> {
+        let prog = self.libbpf_prog()?;
+        let attach_type = unsafe { libbpf_sys::bpf_program__expected_attach_type(prog) };
+        if attach_type != libbpf_sys::BPF_TRACE_ITER || !self.section.contains("/task") {
+            return Err(Error::InvalidInput(format!(
+                "program {} in section {} is not a task iterator",
+                self.name, self.section
+            )));
+        }
+
+        let mut link_info = libbpf_sys::bpf_iter_link_info::default();
+        match target {
+            TaskIterTarget::Thread(tid) => link_info.task.tid = tid,
+            TaskIterTarget::Process(pid) => link_info.task.pid = pid,
+            TaskIterTarget::PidFd(pid_fd) => link_info.task.pid_fd = pid_fd as u32,
+        }
+        let opts = libbpf_sys::bpf_iter_attach_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_iter_attach_opts>() as libbpf_sys::size_t,
+            link_info: &mut link_info,
+            link_info_len: mem::size_of::<libbpf_sys::bpf_iter_link_info>() as u32,
+            ..Default::default()
+        };
+
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_iter(prog, &opts) };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
+    /// Attach this [`ProgramType::Ext`] program so that it replaces the function
+    /// `target_func` of the loaded program referred to by `target_fd`.
+    ///
+    /// The target may differ from the one set with [`OpenProgram::set_attach_target`] at load
+    /// time, as long as the function signatures match.
+    pub fn attach_freplace(&self, target_fd: i32, target_func: &str) -> Result<Link> {
+        let target_func = util::str_to_cstring(target_func)?;
+        let target_func_ptr = target_func.as_ptr();
+        let ptr = unsafe {
+            libbpf_sys::bpf_program__attach_freplace(
+                self.libbpf_prog()?,
+                target_fd,
+                target_func_ptr,
+            )
+        };
+        let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
+        if err != 0 {
+            Err(Error::System(err as i32))
+        } else {
+            Ok(Link::new(ptr))
+        }
+    }
+
     /// Attach this program to [XDP](https://lwn.net/Articles/825998/)
-    pub fn attach_xdp(&mut self, ifindex: i32) -> Result<Link> {
-        let ptr = unsafe { libbpf_sys::bpf_program__attach_xdp(self.ptr, ifindex) };
+    pub fn attach_xdp(&self, ifindex: i32) -> Result<Link> {
+        let ptr = unsafe { libbpf_sys::bpf_program__attach_xdp(self.libbpf_prog()?, ifindex) };
         let err = unsafe { libbpf_sys::libbpf_get_error(ptr as *const _) };
         if err != 0 {
             Err(Error::System(err as i32))
@@ -360,24 +1494,312 @@ impl Program {
         }
     }
 
-    pub fn prog_run(&self, repeat: i32, data_in: &[u8], data_out: Option<&mut [u8]>) -> Result<(u32, Duration)> {
-        let mut retval = 0u32;
-        let mut duration = 0u32;
-        let data_in_c = data_in.as_ptr() as *mut c_void;
-        let (data_out_c, data_out_len_c) = match data_out {
-            Some(d) => {
-                let mut len = d.len() as u32;
-                (d.as_mut_ptr() as *mut c_void, &mut len as *mut u32)
-            },
-            None => (ptr::null_mut(), ptr::null_mut())
+    pub fn prog_run(
+        &self,
+        repeat: i32,
+        data_in: &[u8],
+        data_out: Option<&mut [u8]>,
+    ) -> Result<(u32, Duration)> {
+        self.prog_run_ctx(repeat, data_in, data_out, &[], None)
+    }
+
+    /// Same as [`Program::prog_run`], but also passes the context `ctx_in` to the program and
+    /// stores the context after the run in `ctx_out`. An empty `ctx_in` lets the kernel use
+    /// its default context.
+    ///
+    /// The context layout depends on the program type, see [`SkBuffContext`] for programs
+    /// taking `struct __sk_buff`.
+    pub fn prog_run_ctx(
+        &self,
+        repeat: i32,
+        data_in: &[u8],
+        data_out: Option<&mut [u8]>,
+        ctx_in: &[u8],
+        ctx_out: Option<&mut [u8]>,
+    ) -> Result<(u32, Duration)> {
+        let result = self.prog_run_opts(ProgRunOpts {
+            repeat,
+            data_in,
+            data_out,
+            ctx_in,
+            ctx_out,
+            ..Default::default()
+        })?;
+
+        Ok((result.retval, result.duration))
+    }
+
+    /// Runs this program with the input and settings in `opts`, see [`ProgRunOpts`].
+    ///
+    /// Unlike [`Program::prog_run`] this reports how many bytes of data and context the
+    /// kernel wrote back, e.g. to read the packet left by an XDP program that grew or shrank
+    /// it.
+    pub fn prog_run_opts(&self, opts: ProgRunOpts) -> Result<ProgRunResult> {
+        let (data_out_c, data_out_len_c) = match opts.data_out {
+            Some(d) => (d.as_mut_ptr() as *mut c_void, d.len() as u32),
+            None => (ptr::null_mut(), 0),
+        };
+        let ctx_in_c = if opts.ctx_in.is_empty() {
+            ptr::null()
+        } else {
+            opts.ctx_in.as_ptr() as *const c_void
         };
+        let (ctx_out_c, ctx_out_len_c) = match opts.ctx_out {
+            Some(c) => (c.as_mut_ptr() as *mut c_void, c.len() as u32),
+            None => (ptr::null_mut(), 0),
+        };
+        let mut flags = opts.flags;
+        if opts.cpu.is_some() {
+            flags |= libbpf_sys::BPF_F_TEST_RUN_ON_CPU;
+        }
 
-        let ret = unsafe { libbpf_sys::bpf_prog_test_run(self.fd(), repeat, data_in_c,
-        data_in.len() as u32, data_out_c, data_out_len_c, &mut retval as *mut u32, &mut duration as *mut u32) };
+        let mut run_opts = libbpf_sys::bpf_test_run_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_test_run_opts>() as libbpf_sys::size_t,
+            data_in: opts.data_in.as_ptr() as *const c_void,
+            data_size_in: opts.data_in.len() as u32,
+            data_out: data_out_c,
+            data_size_out: data_out_len_c,
+            ctx_in: ctx_in_c,
+            ctx_size_in: opts.ctx_in.len() as u32,
+            ctx_out: ctx_out_c,
+            ctx_size_out: ctx_out_len_c,
+            repeat: opts.repeat,
+            flags,
+            cpu: opts.cpu.unwrap_or(0),
+            batch_size: opts.batch_size,
+            ..Default::default()
+        };
+
+        let ret = unsafe { libbpf_sys::bpf_prog_test_run_opts(self.fd(), &mut run_opts) };
         if ret != 0 {
             return Err(Error::System(-ret));
         }
 
-        Ok((retval, Duration::from_nanos(duration as u64)))
+        Ok(ProgRunResult {
+            retval: run_opts.retval,
+            duration: Duration::from_nanos(run_opts.duration as u64),
+            data_size_out: run_opts.data_size_out as usize,
+            ctx_size_out: run_opts.ctx_size_out as usize,
+        })
     }
+
+    /// Runs this program `iterations_per_cpu` times with input `data_in` on each CPU the
+    /// calling thread is allowed to run on, and returns the latency distribution per CPU as
+    /// `(cpu, result)` pairs.
+    ///
+    /// The calling thread is pinned to one CPU after the other with `sched_setaffinity()`;
+    /// its original affinity is restored before returning. Latencies are measured by the
+    /// kernel for each run, see [`Program::prog_run`].
+    pub fn benchmark_per_cpu(
+        &self,
+        data_in: &[u8],
+        iterations_per_cpu: u32,
+    ) -> Result<Vec<(u32, BenchResult)>> {
+        if iterations_per_cpu == 0 {
+            return Err(Error::InvalidInput(
+                "iterations_per_cpu must be at least 1".to_string(),
+            ));
+        }
+
+        let this_thread = Pid::from_raw(0);
+        let affinity =
+            sched::sched_getaffinity(this_thread).map_err(|e| Error::System(e as i32))?;
+
+        let run = || {
+            let mut results = Vec::new();
+            for cpu in 0..CpuSet::count() {
+                if !affinity.is_set(cpu).unwrap_or(false) {
+                    continue;
+                }
+
+                let mut cpu_set = CpuSet::new();
+                cpu_set.set(cpu).map_err(|e| Error::System(e as i32))?;
+                sched::sched_setaffinity(this_thread, &cpu_set)
+                    .map_err(|e| Error::System(e as i32))?;
+
+                let mut retval = 0;
+                let mut durations = Vec::with_capacity(iterations_per_cpu as usize);
+                for _ in 0..iterations_per_cpu {
+                    let (ret, duration) = self.prog_run(1, data_in, None)?;
+                    retval = ret;
+                    durations.push(duration);
+                }
+                results.push((cpu as u32, BenchResult::new(retval, durations)));
+            }
+
+            Ok(results)
+        };
+
+        let results = run();
+        let _ = sched::sched_setaffinity(this_thread, &affinity);
+        results
+    }
+}
+
+/// Input and settings of a test run, see [`Program::prog_run_opts`].
+#[derive(Debug, Default)]
+pub struct ProgRunOpts<'a> {
+    /// Number of times to run the program, 0 runs it once.
+    pub repeat: i32,
+    pub data_in: &'a [u8],
+    /// Buffer receiving the data after the run, e.g. the modified packet.
+    pub data_out: Option<&'a mut [u8]>,
+    /// Context passed to the program, empty to let the kernel use its default context.
+    pub ctx_in: &'a [u8],
+    /// Buffer receiving the context after the run.
+    pub ctx_out: Option<&'a mut [u8]>,
+    /// `BPF_F_TEST_*` flags, e.g. `BPF_F_TEST_XDP_LIVE_FRAMES`.
+    pub flags: u32,
+    /// CPU to run the program on, only supported for raw tracepoint programs. `None` runs it
+    /// on the current CPU.
+    pub cpu: Option<u32>,
+    /// Number of frames sent per batch with `BPF_F_TEST_XDP_LIVE_FRAMES`, 0 for the kernel
+    /// default.
+    pub batch_size: u32,
+}
+
+/// Outcome of [`Program::prog_run_opts`].
+#[derive(Clone, Debug)]
+pub struct ProgRunResult {
+    /// Return value of the program in the last run.
+    pub retval: u32,
+    /// Average duration of a run.
+    pub duration: Duration,
+    /// Number of bytes of data the program left, which may differ from the input length.
+    pub data_size_out: usize,
+    /// Number of bytes of context written to `ctx_out`.
+    pub ctx_size_out: usize,
+}
+
+/// Latency distribution of a program, see [`Program::benchmark_per_cpu`].
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    /// Number of runs.
+    pub iterations: u32,
+    /// Return value of the program in the last run.
+    pub retval: u32,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// Median latency.
+    pub p50: Duration,
+    pub p99: Duration,
+}
+
+impl BenchResult {
+    fn new(retval: u32, mut durations: Vec<Duration>) -> Self {
+        durations.sort();
+        let percentile = |p: usize| durations[(durations.len() - 1) * p / 100];
+        let total: Duration = durations.iter().sum();
+
+        BenchResult {
+            iterations: durations.len() as u32,
+            retval,
+            min: durations[0],
+            max: durations[durations.len() - 1],
+            mean: total / durations.len() as u32,
+            p50: percentile(50),
+            p99: percentile(99),
+        }
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        // Programs loaded from an `Object` are closed by libbpf along with it.
+        if self.ptr.is_null() {
+            let _ = unistd::close(self.fd);
+        }
+    }
+}
+
+/// Licenses the kernel treats as GPL compatible, see `license_is_gpl_compatible()` in the
+/// kernel sources.
+const GPL_COMPATIBLE_LICENSES: &[&str] = &[
+    "GPL",
+    "GPL v2",
+    "GPL and additional rights",
+    "Dual BSD/GPL",
+    "Dual MIT/GPL",
+    "Dual MPL/GPL",
+];
+
+/// Returns whether verifier log `log` reports a call to a GPL-only helper from a program
+/// whose license is not GPL compatible.
+pub(crate) fn is_gpl_violation(log: &str) -> bool {
+    log.contains("cannot call GPL-restricted function")
+}
+
+/// Loads `insns` as a new program of type `prog_type` with license `license` (e.g.
+/// `"GPL"`), without going through an [`Object`].
+///
+/// Instructions are passed to the kernel as is. Map references must already be resolved to
+/// file descriptors, which is the case for instructions returned by [`Program::dump_insns`].
+///
+/// Unlike programs loaded from an [`Object`], whose license always comes from the object's
+/// `license` section, the license can be chosen freely here. If the program calls GPL-only
+/// helpers and `license` is not GPL compatible, this fails with [`Error::InvalidInput`].
+pub fn create_program_from_insns(
+    prog_type: ProgramType,
+    insns: &[libbpf_sys::bpf_insn],
+    license: &str,
+) -> Result<Program> {
+    let prog_type = prog_type as u32;
+    let license_c = util::str_to_cstring(license)?;
+    let load = |opts: *mut libbpf_sys::bpf_prog_load_opts| unsafe {
+        libbpf_sys::bpf_prog_load(
+            prog_type,
+            ptr::null(),
+            license_c.as_ptr(),
+            insns.as_ptr(),
+            insns.len() as libbpf_sys::size_t,
+            opts,
+        )
+    };
+
+    let fd = load(ptr::null_mut());
+    if fd < 0 {
+        if !GPL_COMPATIBLE_LICENSES.contains(&license) {
+            // Load again with a verifier log to tell license violations apart. Kernels since
+            // 6.4 report how large the log is when passed no buffer, older ones get the
+            // largest buffer they accept.
+            let mut opts = libbpf_sys::bpf_prog_load_opts {
+                sz: mem::size_of::<libbpf_sys::bpf_prog_load_opts>() as libbpf_sys::size_t,
+                log_level: 1,
+                ..Default::default()
+            };
+            let ret = load(&mut opts);
+            if ret >= 0 {
+                let _ = unistd::close(ret);
+            }
+
+            let mut log_buf = match opts.log_true_size {
+                0 => vec![0u8; DEFAULT_LOG_BUF_SIZE],
+                size => vec![0u8; size as usize],
+            };
+            opts.log_size = log_buf.len() as u32;
+            opts.log_buf = log_buf.as_mut_ptr() as *mut _;
+            let ret = load(&mut opts);
+            if ret >= 0 {
+                let _ = unistd::close(ret);
+            }
+            if is_gpl_violation(&String::from_utf8_lossy(&log_buf)) {
+                return Err(Error::InvalidInput(format!(
+                    "program calls GPL-only helpers but license {:?} is not GPL compatible",
+                    license
+                )));
+            }
+        }
+
+        return Err(Error::System(-fd));
+    }
+
+    Ok(Program {
+        ptr: ptr::null_mut(),
+        fd,
+        name: String::new(),
+        section: String::new(),
+        log: None,
+        pin_path: None,
+    })
 }
@@ -74,7 +74,7 @@ macro_rules! gen_info_impl {
     };
 }
 
-fn name_arr_to_string(a: &[c_char], default: &str) -> String {
+pub(crate) fn name_arr_to_string(a: &[c_char], default: &str) -> String {
     let converted_arr: Vec<u8> = a
         .iter()
         .take_while(|x| **x != 0)
@@ -124,10 +124,13 @@ pub struct ProgramInfo {
     pub prog_tags: u64,
     pub run_time_ns: u64,
     pub run_cnt: u64,
+    /// Number of instructions the verifier processed, which is what the 1M instruction limit
+    /// applies to. 0 on kernels older than 5.16.
+    pub verified_insns: u32,
 }
 
 impl ProgramInfo {
-    fn from_uapi(_fd: i32, s: libbpf_sys::bpf_prog_info) -> Option<Self> {
+    pub(crate) fn from_uapi(_fd: i32, s: libbpf_sys::bpf_prog_info) -> Option<Self> {
         let name = name_arr_to_string(&s.name, "(?)");
         let ty = match ProgramType::try_from(s.type_) {
             Ok(ty) => ty,
@@ -169,6 +172,7 @@ impl ProgramInfo {
             prog_tags: s.prog_tags,
             run_time_ns: s.run_time_ns,
             run_cnt: s.run_cnt,
+            verified_insns: s.verified_insns,
         })
     }
 }
@@ -201,7 +205,7 @@ pub struct MapInfo {
 }
 
 impl MapInfo {
-    fn from_uapi(_fd: i32, s: libbpf_sys::bpf_map_info) -> Option<Self> {
+    pub(crate) fn from_uapi(_fd: i32, s: libbpf_sys::bpf_map_info) -> Option<Self> {
         let name = name_arr_to_string(&s.name, "(?)");
         let ty = match MapType::try_from(s.type_) {
             Ok(ty) => ty,
@@ -262,24 +266,29 @@ gen_info_impl!(
     libbpf_sys::bpf_btf_get_fd_by_id
 );
 
+#[derive(Clone)]
 pub struct RawTracepointLinkInfo {
     pub name: String,
 }
 
+#[derive(Clone)]
 pub struct TracingLinkInfo {
     pub attach_type: ProgramAttachType,
 }
 
+#[derive(Clone)]
 pub struct CgroupLinkInfo {
     pub cgroup_id: u64,
     pub attach_type: ProgramAttachType,
 }
 
+#[derive(Clone)]
 pub struct NetNsLinkInfo {
     pub ino: u32,
     pub attach_type: ProgramAttachType,
 }
 
+#[derive(Clone)]
 pub enum LinkTypeInfo {
     RawTracepoint(RawTracepointLinkInfo),
     Tracing(TracingLinkInfo),
@@ -290,6 +299,7 @@ pub enum LinkTypeInfo {
 }
 
 /// Information about a BPF link
+#[derive(Clone)]
 pub struct LinkInfo {
     pub info: LinkTypeInfo,
     pub id: u32,
@@ -297,7 +307,7 @@ pub struct LinkInfo {
 }
 
 impl LinkInfo {
-    fn from_uapi(fd: i32, mut s: libbpf_sys::bpf_link_info) -> Option<Self> {
+    pub(crate) fn from_uapi(fd: i32, mut s: libbpf_sys::bpf_link_info) -> Option<Self> {
         let type_info = match s.type_ {
             libbpf_sys::BPF_LINK_TYPE_RAW_TRACEPOINT => {
                 let mut buf = [0; 256];
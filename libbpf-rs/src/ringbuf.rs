@@ -1,10 +1,16 @@
 use core::ffi::c_void;
 use std::boxed::Box;
+use std::cell::Cell;
 use std::os::raw::c_ulong;
 use std::ptr;
+use std::rc::Rc;
 use std::slice;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+use nix::unistd;
+
 use crate::*;
 
 struct RingBufferCallback {
@@ -140,6 +146,27 @@ impl RingBuffer {
         }
     }
 
+    /// Number of bytes produced into all open ring buffers that have not been consumed yet.
+    ///
+    /// Consumers can compare this to the ring buffer sizes to tell how far behind they are
+    /// before events start being dropped. The value is a snapshot of positions that
+    /// programs and consumers keep updating, so it may be outdated as soon as it is
+    /// returned.
+    pub fn available_bytes(&self) -> u64 {
+        assert!(!self.ptr.is_null());
+
+        (0..self._cbs.len())
+            .map(|idx| {
+                let ring = unsafe { libbpf_sys::ring_buffer__ring(self.ptr, idx as u32) };
+                if ring.is_null() {
+                    0
+                } else {
+                    unsafe { libbpf_sys::ring__avail_data_size(ring) as u64 }
+                }
+            })
+            .sum()
+    }
+
     /// Greedily consume from all open ring buffers, calling the registered
     /// callback for each one. Consumes continually until we run out of events
     /// to consume or one of the callbacks returns a non-zero integer.
@@ -156,6 +183,56 @@ impl RingBuffer {
     }
 }
 
+impl RingBuffer {
+    /// Spawn a thread that polls the ringbuf `map` and forwards every event over the returned
+    /// channel.
+    ///
+    /// The thread owns its own reference to `map`, so it keeps running after the [`Object`]
+    /// is dropped. It stops, and the [`JoinHandle`] resolves to `Ok(())`, once the
+    /// [`Receiver`] has been dropped. Note that this is only noticed when the next event
+    /// arrives. If polling fails the thread stops and returns the error.
+    #[allow(clippy::type_complexity)]
+    pub fn spawn_consumer(map: &dyn MapOps) -> Result<(JoinHandle<Result<()>>, Receiver<Vec<u8>>)> {
+        if map.map_type() != MapType::RingBuf {
+            return Err(Error::InvalidInput("Must use a RingBuf map".into()));
+        }
+
+        let fd = unistd::dup(map.fd()).map_err(|e| Error::System(e as i32))?;
+        let (sender, receiver) = channel();
+
+        let handle = thread::spawn(move || {
+            let hung_up = Rc::new(Cell::new(false));
+            let cb_hung_up = hung_up.clone();
+            let callback = move |data: &[u8]| -> i32 {
+                if sender.send(data.to_vec()).is_err() {
+                    cb_hung_up.set(true);
+                    // Negative return values stop consumption
+                    return -1;
+                }
+                0
+            };
+
+            let mut builder = RingBufferBuilder::new();
+            builder
+                .fd_callbacks
+                .push((fd, RingBufferCallback::new(callback)));
+            let result = builder.build().and_then(|ringbuf| loop {
+                match ringbuf.poll(Duration::from_millis(100)) {
+                    Ok(()) if !hung_up.get() => continue,
+                    Err(e) if !hung_up.get() => break Err(e),
+                    _ => break Ok(()),
+                }
+            });
+
+            // Ignore errors b/c can't really recover from failure
+            let _ = unistd::close(fd);
+            result
+        });
+
+        Ok((handle, receiver))
+    }
+}
+
 impl Drop for RingBuffer {
     fn drop(&mut self) {
         unsafe {
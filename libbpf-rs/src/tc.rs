@@ -0,0 +1,133 @@
+use std::mem;
+
+use nix::libc;
+
+use crate::*;
+
+/// Where on an interface a [`TcHook`] attaches programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcAttachPoint {
+    /// Packets received on the interface.
+    Ingress,
+    /// Packets sent out of the interface.
+    Egress,
+}
+
+impl TcAttachPoint {
+    fn to_raw(self) -> libbpf_sys::bpf_tc_attach_point {
+        match self {
+            TcAttachPoint::Ingress => libbpf_sys::BPF_TC_INGRESS,
+            TcAttachPoint::Egress => libbpf_sys::BPF_TC_EGRESS,
+        }
+    }
+}
+
+/// A traffic control hook on the `clsact` qdisc of an interface, used to attach
+/// [`ProgramType::SchedCls`] programs as direct-action filters.
+///
+/// Filters attached through a `TcHook` are owned by the interface, not by this object: they
+/// stay attached after it is dropped until [`TcHook::detach()`] or [`TcHook::destroy()`] is
+/// called, or the interface goes away.
+pub struct TcHook {
+    hook: libbpf_sys::bpf_tc_hook,
+    opts: libbpf_sys::bpf_tc_opts,
+}
+
+impl TcHook {
+    /// Describes the hook at `attach_point` of the interface with index `ifindex`. Nothing is
+    /// changed on the system until [`TcHook::create()`] or [`TcHook::attach()`] is called.
+    pub fn new(ifindex: i32, attach_point: TcAttachPoint) -> Self {
+        TcHook {
+            hook: libbpf_sys::bpf_tc_hook {
+                sz: mem::size_of::<libbpf_sys::bpf_tc_hook>() as libbpf_sys::size_t,
+                ifindex,
+                attach_point: attach_point.to_raw(),
+                ..Default::default()
+            },
+            opts: libbpf_sys::bpf_tc_opts {
+                sz: mem::size_of::<libbpf_sys::bpf_tc_opts>() as libbpf_sys::size_t,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Priority of the filter, lower values run first. Defaults to 0, which lets the kernel
+    /// pick one.
+    pub fn priority(&mut self, priority: u32) -> &mut Self {
+        self.opts.priority = priority;
+        self
+    }
+
+    /// Handle of the filter. Defaults to 0, which lets the kernel pick one.
+    pub fn handle(&mut self, handle: u32) -> &mut Self {
+        self.opts.handle = handle;
+        self
+    }
+
+    /// Creates the `clsact` qdisc of the interface. It is fine if it already exists.
+    pub fn create(&mut self) -> Result<&mut Self> {
+        let ret = unsafe { libbpf_sys::bpf_tc_hook_create(&mut self.hook) };
+        if ret < 0 && ret != -libc::EEXIST {
+            return Err(Error::System(-ret));
+        }
+
+        Ok(self)
+    }
+
+    /// Attaches `prog`, which must be a [`ProgramType::SchedCls`] program, to this hook.
+    ///
+    /// The hook must have been created with [`TcHook::create()`] first.
+    pub fn attach(&mut self, prog: &Program) -> Result<&mut Self> {
+        if !matches!(prog.prog_type(), ProgramType::SchedCls) {
+            return Err(Error::InvalidInput(format!(
+                "program {} is not a sched_cls program",
+                prog.name()
+            )));
+        }
+
+        self.opts.prog_fd = prog.fd();
+        self.opts.prog_id = 0;
+        let ret = unsafe { libbpf_sys::bpf_tc_attach(&self.hook, &mut self.opts) };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+
+        Ok(self)
+    }
+
+    /// Detaches the filter previously attached with [`TcHook::attach()`].
+    pub fn detach(&mut self) -> Result<()> {
+        // The filter is looked up by handle and priority only, libbpf rejects anything else
+        let opts = libbpf_sys::bpf_tc_opts {
+            sz: mem::size_of::<libbpf_sys::bpf_tc_opts>() as libbpf_sys::size_t,
+            handle: self.opts.handle,
+            priority: self.opts.priority,
+            ..Default::default()
+        };
+        let ret = unsafe { libbpf_sys::bpf_tc_detach(&self.hook, &opts) };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+
+        self.opts.prog_fd = 0;
+        self.opts.prog_id = 0;
+        Ok(())
+    }
+
+    /// Removes all filters of this hook's attach point.
+    ///
+    /// The `clsact` qdisc itself stays around, as the other attach point may still be in use.
+    pub fn destroy(&mut self) -> Result<()> {
+        let ret = unsafe { libbpf_sys::bpf_tc_hook_destroy(&mut self.hook) };
+        if ret < 0 {
+            return Err(Error::System(-ret));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the id of the attached program, 0 if none was attached through this hook.
+    pub fn prog_id(&self) -> u32 {
+        self.opts.prog_id
+    }
+}
@@ -1,7 +1,13 @@
+use std::env;
 use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
 use std::os::raw::c_char;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 
+use nix::errno;
+
 use crate::*;
 
 pub fn str_to_cstring(s: &str) -> Result<CString> {
@@ -27,3 +33,80 @@ pub fn c_ptr_to_string(p: *const c_char) -> Result<String> {
         .map_err(|e| Error::Internal(e.to_string()))?
         .to_owned())
 }
+
+pub fn io_error_to_error(e: io::Error) -> Error {
+    Error::System(e.raw_os_error().unwrap_or(errno::Errno::EIO as i32))
+}
+
+pub fn set_mode<P: AsRef<Path>>(path: P, mode: u32) -> Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(io_error_to_error)
+}
+
+/// Reads the numeric field `key` (e.g. `memlock`) from `/proc/self/fdinfo/<fd>`.
+pub fn fdinfo_field(fd: i32, key: &str) -> Result<u64> {
+    let fdinfo =
+        fs::read_to_string(format!("/proc/self/fdinfo/{}", fd)).map_err(io_error_to_error)?;
+    fdinfo
+        .lines()
+        .find_map(|line| {
+            let mut kv = line.splitn(2, ':');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if k == key => v.trim().parse().ok(),
+                _ => None,
+            }
+        })
+        .ok_or_else(|| Error::Internal(format!("no {} in fdinfo of fd {}", key, fd)))
+}
+
+/// Parses a leading `major.minor.patch` the way `sscanf("%u.%u.%u")` does.
+fn parse_kernel_version(s: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = s.splitn(3, '.').map(|part| {
+        let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        part[..digits].parse::<u32>().ok()
+    });
+
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// Returns the kernel version libbpf detected, as `(major, minor, patch)`.
+///
+/// This is the version libbpf puts into `LINUX_KERNEL_VERSION` externs and passes to the
+/// kernel when loading kprobe programs. Like libbpf, the version is taken from
+/// `/proc/version_signature` on Ubuntu, from the `uname` version string on Debian and from
+/// the `uname` release otherwise. The patch level is capped at 255, as in
+/// `KERNEL_VERSION()`.
+///
+/// The `LINUX_VERSION_CODE` environment variable, if set, overrides the detection. It holds
+/// the version encoded like `KERNEL_VERSION()` does, e.g. `393984` for 6.3.0, and fails with
+/// [`Error::InvalidInput`] if it isn't a number. libbpf itself doesn't read the variable, so
+/// the override only applies to this function.
+pub fn detected_kernel_version() -> Result<(u32, u32, u32)> {
+    if let Some(code) = env::var_os("LINUX_VERSION_CODE") {
+        let code = code
+            .to_str()
+            .and_then(|code| code.trim().parse::<u32>().ok())
+            .ok_or_else(|| Error::InvalidInput(format!("invalid LINUX_VERSION_CODE {:?}", code)))?;
+        return Ok((code >> 16, (code >> 8) & 0xff, code & 0xff));
+    }
+
+    let ubuntu = fs::read_to_string("/proc/version_signature")
+        .ok()
+        .and_then(|sig| sig.split_whitespace().nth(2).and_then(parse_kernel_version));
+
+    let info = nix::sys::utsname::uname();
+    let debian = || {
+        let version = info.version();
+        version
+            .find("Debian ")
+            .and_then(|i| parse_kernel_version(&version[i + "Debian ".len()..]))
+    };
+
+    let (major, minor, patch) = ubuntu
+        .or_else(debian)
+        .or_else(|| parse_kernel_version(info.release()))
+        .ok_or_else(|| {
+            Error::Internal(format!("failed to parse kernel version {}", info.release()))
+        })?;
+
+    Ok((major, minor, std::cmp::min(patch, 255)))
+}
@@ -2,7 +2,7 @@ use std::ffi::c_void;
 use std::mem;
 use std::path::Path;
 
-use nix::errno;
+use nix::{errno, libc};
 
 use crate::*;
 
@@ -30,3 +30,87 @@ pub fn bpf_obj_get_info_by_fd<T>(fd: i32) -> Result<T> {
     }
     Ok(info)
 }
+
+/// Returns the number of possible CPUs, as read by libbpf from
+/// `/sys/devices/system/cpu/possible`. Per-CPU maps hold one value for each of them.
+pub fn num_possible_cpus() -> Result<usize> {
+    let ret = unsafe { libbpf_sys::libbpf_num_possible_cpus() };
+    if ret < 0 {
+        // Error code is returned negative, flip to positive to match errno
+        return Err(Error::System(-ret));
+    }
+    Ok(ret as usize)
+}
+
+/// Sets the soft and hard `RLIMIT_MEMLOCK` of the process to `bytes`, e.g.
+/// `libc::RLIM_INFINITY`.
+///
+/// Linux 5.10 and earlier charge BPF maps and programs against this limit, so loading fails
+/// with `EPERM` once it is exceeded. Newer kernels account BPF memory to the memory cgroup
+/// instead. libbpf raises the limit by itself on old kernels for everything it creates, this
+/// is for BPF objects created by other means or to pick a limit other than infinity. Lowering
+/// the hard limit cannot be undone without `CAP_SYS_RESOURCE`, see also [`init_with()`],
+/// which only ever raises it.
+pub fn set_memlock_rlimit(bytes: u64) -> Result<()> {
+    setrlimit_memlock(bytes, bytes)
+}
+
+/// Sets the soft `RLIMIT_MEMLOCK` to `cur` and the hard one to `max`.
+pub(crate) fn setrlimit_memlock(cur: u64, max: u64) -> Result<()> {
+    let rlimit = libc::rlimit {
+        rlim_cur: cur,
+        rlim_max: max,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlimit) } != 0 {
+        return Err(Error::System(errno::errno()));
+    }
+    Ok(())
+}
+
+/// Probe whether BPF helper `helper_id` can be called from programs of type `prog_type`.
+pub fn probe_helper(prog_type: libbpf_sys::bpf_prog_type, helper_id: u32) -> Result<bool> {
+    let ret =
+        unsafe { libbpf_sys::libbpf_probe_bpf_helper(prog_type, helper_id, std::ptr::null()) };
+    if ret < 0 {
+        return Err(Error::System(-ret));
+    }
+    Ok(ret == 1)
+}
+
+/// Probe whether the running kernel supports programs of type `prog_type`, by loading a
+/// trivial program of that type.
+///
+/// Returns [`Error::System`] with `EOPNOTSUPP` for types libbpf does not know how to probe
+/// and [`Error::InvalidInput`] for [`ProgramType::Unknown`].
+pub fn probe_program_type(prog_type: ProgramType) -> Result<bool> {
+    if let ProgramType::Unknown = prog_type {
+        return Err(Error::InvalidInput(
+            "cannot probe unknown program type".to_string(),
+        ));
+    }
+
+    let ret = unsafe { libbpf_sys::libbpf_probe_bpf_prog_type(prog_type as u32, std::ptr::null()) };
+    if ret < 0 {
+        return Err(Error::System(-ret));
+    }
+    Ok(ret == 1)
+}
+
+/// Probe whether the running kernel supports maps of type `map_type`, by creating a small
+/// map of that type.
+///
+/// Returns [`Error::System`] with `EOPNOTSUPP` for types libbpf does not know how to probe,
+/// e.g. [`MapType::Unspec`], and [`Error::InvalidInput`] for [`MapType::Unknown`].
+pub fn probe_map_type(map_type: MapType) -> Result<bool> {
+    if map_type == MapType::Unknown {
+        return Err(Error::InvalidInput(
+            "cannot probe unknown map type".to_string(),
+        ));
+    }
+
+    let ret = unsafe { libbpf_sys::libbpf_probe_bpf_map_type(map_type as u32, std::ptr::null()) };
+    if ret < 0 {
+        return Err(Error::System(-ret));
+    }
+    Ok(ret == 1)
+}
@@ -0,0 +1,16 @@
+#include "vmlinux.h"
+#include <bpf/bpf_helpers.h>
+
+SEC("cgroup_skb/ingress")
+int allow_ingress(struct __sk_buff *skb)
+{
+  return 1;
+}
+
+SEC("cgroup_skb/ingress")
+int allow_ingress_too(struct __sk_buff *skb)
+{
+  return 1;
+}
+
+char _license[] SEC("license") = "GPL";
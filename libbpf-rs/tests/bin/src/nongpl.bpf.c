@@ -0,0 +1,14 @@
+#include "vmlinux.h"
+#include <bpf/bpf_helpers.h>
+
+static long (*trace_printk)(const char *fmt, __u32 fmt_size) = (void *)6;
+
+SEC("socket")
+int call_gpl_helper(struct __sk_buff *skb)
+{
+  /* bpf_trace_printk() is GPL-only */
+  trace_printk(NULL, 0);
+  return 0;
+}
+
+char _license[] SEC("license") = "Proprietary";
@@ -1,7 +1,12 @@
 use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
 use std::fs;
 use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::ptr;
 use std::sync::mpsc::channel;
 use std::time::Duration;
 
@@ -9,7 +14,12 @@ use nix::errno;
 use plain::Plain;
 use scopeguard::defer;
 
-use libbpf_rs::{Iter, MapFlags, Object, ObjectBuilder};
+use libbpf_rs::{
+    Btf, BtfKind, CgroupAttachFlags, CgroupStorageKey, Iter, KprobeOpts, Link, Map, MapCreateFlags,
+    MapCreateOpts, MapFlags, MapOps, MapType, Object, ObjectBinding, ObjectBuilder,
+    PerfEventBuilder, PinnedMap, ProgRunOpts, Program, ProgramAttachType, ProgramType,
+    SkBuffContext, TaskIterTarget, TaskStorageKey, TcAttachPoint, TcHook, UprobeOpts,
+};
 
 fn get_test_object_path(filename: &str) -> PathBuf {
     let mut path = PathBuf::new();
@@ -51,6 +61,23 @@ fn bump_rlimit_mlock() {
     );
 }
 
+#[test]
+fn test_detected_kernel_version() {
+    let (major, _, patch) = libbpf_rs::detected_kernel_version().expect("failed to get version");
+    // BPF itself needs at least 3.18
+    assert!(major >= 3);
+    assert!(patch <= 255);
+
+    // Checked in the same test as the detection, as the override applies process wide
+    std::env::set_var("LINUX_VERSION_CODE", "393984");
+    let version = libbpf_rs::detected_kernel_version();
+    std::env::set_var("LINUX_VERSION_CODE", "6.3.0");
+    let invalid = libbpf_rs::detected_kernel_version();
+    std::env::remove_var("LINUX_VERSION_CODE");
+    assert_eq!(version.expect("failed to get version"), (6, 3, 0));
+    assert!(matches!(invalid, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
 #[test]
 fn test_object_build_and_load() {
     bump_rlimit_mlock();
@@ -70,6 +97,24 @@ fn test_object_build_from_memory() {
     assert!(name == "memory name");
 }
 
+#[test]
+fn test_object_load_from_memory() {
+    bump_rlimit_mlock();
+
+    let contents =
+        fs::read(get_test_object_path("runqslower.bpf.o")).expect("failed to read object file");
+    let mut builder = ObjectBuilder::default();
+    let open_obj = builder
+        .open_memory("runqslower", &contents)
+        .expect("failed to open object");
+    // The data is only needed while opening
+    drop(contents);
+
+    let obj = open_obj.load().expect("failed to load object");
+    assert!(obj.prog("handle__sched_wakeup").is_some());
+    assert!(obj.map("start").is_some());
+}
+
 #[test]
 fn test_object_name() {
     let obj_path = get_test_object_path("runqslower.bpf.o");
@@ -80,6 +125,145 @@ fn test_object_name() {
     assert!(obj_name == "test name");
 }
 
+#[test]
+fn test_object_set_object_name() {
+    bump_rlimit_mlock();
+
+    let mut builder = ObjectBuilder::default();
+    assert!(builder.set_object_name("1bad").is_err());
+    assert!(builder.set_object_name("bad name").is_err());
+    assert!(builder.set_object_name("").is_err());
+
+    builder
+        .set_object_name("my_obj.v2")
+        .expect("failed to set object name");
+    let bytes = fs::read(get_test_object_path("runqslower.bpf.o")).expect("failed to read object");
+    let open_obj = builder
+        .open_memory("", &bytes)
+        .expect("failed to open object");
+    assert_eq!(
+        open_obj.name().expect("failed to get object name"),
+        "my_obj.v2"
+    );
+
+    let obj = get_test_object("runqslower.bpf.o");
+    assert_eq!(obj.name(), "runqslower");
+}
+
+#[test]
+fn test_object_map_definitions() {
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to build object");
+    let defs = obj
+        .map_definitions()
+        .expect("failed to get map definitions");
+
+    // This will include .rodata, so our expected count is 3, not 2
+    assert_eq!(defs.len(), 3);
+    let start = defs
+        .iter()
+        .find(|def| def.name == "start")
+        .expect("failed to find map");
+    assert_eq!(start.map_type, MapType::Hash);
+    assert_eq!(start.key_size, 4);
+    assert_eq!(start.value_size, 8);
+    assert_eq!(start.max_entries, 10240);
+    let events = defs
+        .iter()
+        .find(|def| def.name == "events")
+        .expect("failed to find map");
+    assert_eq!(events.map_type, MapType::PerfEventArray);
+}
+
+#[test]
+fn test_object_program_sections() {
+    let open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("runqslower.bpf.o"))
+        .expect("failed to open object");
+
+    let sections = open_obj
+        .program_sections()
+        .expect("failed to get program sections");
+    assert_eq!(sections.len(), 3);
+    assert!(sections.contains(&(
+        "handle__sched_wakeup".to_string(),
+        "tp_btf/sched_wakeup".to_string()
+    )));
+}
+
+#[test]
+fn test_object_map_autocreate() {
+    bump_rlimit_mlock();
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to open object");
+
+    // `events` is only used by `handle__sched_switch`
+    let events = open_obj.map_mut("events").expect("failed to find map");
+    assert!(events.autocreate());
+    events
+        .set_autocreate(false)
+        .expect("failed to set autocreate");
+    assert!(!events.autocreate());
+    open_obj
+        .prog_mut("handle__sched_switch")
+        .expect("failed to find program")
+        .set_autoload(false)
+        .expect("failed to set autoload");
+
+    let obj = open_obj.load().expect("failed to load object");
+    obj.map("start").expect("failed to find map");
+    assert!(obj.map("events").is_none());
+}
+
+#[test]
+fn test_object_map_resize() {
+    bump_rlimit_mlock();
+
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("ringbuf.bpf.o"))
+        .expect("failed to open object");
+    let ringbuf = open_obj.map_mut("ringbuf1").expect("failed to find map");
+    assert_eq!(ringbuf.max_entries(), 4096);
+    // Ring buffers are rounded up to a power-of-two multiple of the page size
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u32;
+    assert_eq!(
+        ringbuf.resize(2 * page_size + 1).expect("failed to resize"),
+        4 * page_size
+    );
+    assert_eq!(ringbuf.max_entries(), 4 * page_size);
+
+    let obj = open_obj.load().expect("failed to load object");
+    let info = obj
+        .map("ringbuf1")
+        .expect("failed to find map")
+        .info()
+        .expect("failed to get map info");
+    assert_eq!(info.max_entries, 4 * page_size);
+}
+
+#[test]
+fn test_object_map_set_inner_map() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let inner = obj.map("start").expect("failed to find map");
+
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("runqslower.bpf.o"))
+        .expect("failed to open object");
+    let start = open_obj.map_mut("start").expect("failed to find map");
+    // `start` is a plain hash map
+    let err = start
+        .set_inner_map(inner)
+        .expect_err("set inner map of a hash map");
+    assert!(matches!(err, libbpf_rs::Error::InvalidInput(_)));
+}
+
 #[test]
 fn test_object_maps() {
     bump_rlimit_mlock();
@@ -170,6 +354,95 @@ fn test_object_map_lookup_flags() {
         .is_err());
 }
 
+#[test]
+fn test_object_map_swap() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+
+    // Swapping with EXIST on a missing key fails and leaves the map untouched
+    assert!(start.swap(&[1, 2, 3, 4], &[1; 8], MapFlags::EXIST).is_err());
+    assert!(start
+        .lookup(&[1, 2, 3, 4], MapFlags::empty())
+        .expect("failed to read map")
+        .is_none());
+
+    let prev = start
+        .swap(&[1, 2, 3, 4], &[1; 8], MapFlags::empty())
+        .expect("failed to swap");
+    assert!(prev.is_none());
+    let prev = start
+        .swap(&[1, 2, 3, 4], &[2; 8], MapFlags::empty())
+        .expect("failed to swap");
+    assert_eq!(prev, Some(vec![1; 8]));
+    let val = start
+        .lookup(&[1, 2, 3, 4], MapFlags::empty())
+        .expect("failed to read map")
+        .expect("failed to find key");
+    assert_eq!(val, vec![2; 8]);
+
+    assert!(start.swap(&[1, 2, 3, 4], &[1], MapFlags::empty()).is_err());
+}
+
+#[test]
+fn test_object_map_create_flags() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    assert!(start.create_flags().is_empty());
+
+    let path = "/sys/fs/bpf/myrodata";
+    let rodata = obj
+        .maps_iter_mut()
+        .find(|map| map.name().ends_with(".rodata"))
+        .expect("failed to find map");
+    assert!(rodata.create_flags().contains(MapCreateFlags::RDONLY_PROG));
+    rodata.pin(path).expect("failed to pin map");
+
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    let pinned = PinnedMap::try_from_path(path).expect("failed to open pinned map");
+    assert_eq!(pinned.create_flags(), rodata.create_flags());
+}
+
+#[test]
+fn test_object_map_max_entries() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map_mut("start").expect("failed to find map");
+    assert_eq!(start.max_entries(), 10240);
+
+    let path = "/sys/fs/bpf/mymaxentries";
+    start.pin(path).expect("failed to pin map");
+
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    let pinned = PinnedMap::try_from_path(path).expect("failed to open pinned map");
+    assert_eq!(pinned.max_entries(), 10240);
+
+    let map = Map::from_id(start.info().expect("failed to get map info").id)
+        .expect("failed to open map by id");
+    assert_eq!(map.max_entries(), 10240);
+
+    let opts = MapCreateOpts {
+        map_flags: MapCreateFlags::MMAPABLE,
+        ..Default::default()
+    };
+    let map =
+        Map::create(MapType::Array, "maxentries", 4, 8, 3, opts).expect("failed to create map");
+    assert_eq!(map.max_entries(), 3);
+    assert_eq!(map.create_flags(), MapCreateFlags::MMAPABLE);
+}
+
 #[test]
 fn test_object_map_key_iter() {
     bump_rlimit_mlock();
@@ -203,351 +476,2997 @@ fn test_object_map_key_iter() {
 }
 
 #[test]
-fn test_object_map_key_iter_empty() {
+fn test_map_try_keys() {
+    bump_rlimit_mlock();
+
+    let map = Map::create(MapType::Hash, "try_keys", 4, 4, 8, MapCreateOpts::default())
+        .expect("failed to create map");
+    for i in 0u32..3 {
+        map.update(&i.to_ne_bytes(), &i.to_ne_bytes(), MapFlags::empty())
+            .expect("failed to write");
+    }
+    let keys = map
+        .try_keys()
+        .collect::<libbpf_rs::Result<HashSet<_>>>()
+        .expect("failed to read keys");
+    assert_eq!(keys.len(), 3);
+
+    // Queues have no keys to iterate, which is an error rather than an empty map
+    let queue = Map::create(
+        MapType::Queue,
+        "try_keys_q",
+        0,
+        4,
+        8,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+    assert_eq!(queue.keys().count(), 0);
+    let mut keys = queue.try_keys();
+    assert!(matches!(
+        keys.next(),
+        Some(Err(libbpf_rs::Error::System(_)))
+    ));
+    assert!(keys.next().is_none());
+}
+
+#[test]
+fn test_object_map_to_hashmap() {
     bump_rlimit_mlock();
 
     let obj = get_test_object("runqslower.bpf.o");
     let start = obj.map("start").expect("failed to find map");
+    assert!(start.to_hashmap().expect("failed to read map").is_empty());
 
-    let mut count = 0;
-    for _ in start.keys() {
-        count += 1;
+    start
+        .update(&[1, 2, 3, 4], &[1; 8], MapFlags::empty())
+        .expect("failed to write");
+    start
+        .update(&[1, 2, 3, 5], &[2; 8], MapFlags::empty())
+        .expect("failed to write");
+
+    let entries = start.to_hashmap().expect("failed to read map");
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[&vec![1, 2, 3, 4]], vec![1; 8]);
+    assert_eq!(entries[&vec![1, 2, 3, 5]], vec![2; 8]);
+}
+
+#[test]
+fn test_object_map_approximate_count() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    assert_eq!(start.approximate_count().expect("failed to count"), 0);
+
+    // Enough entries to need more than one batch
+    for i in 0..1500u32 {
+        start
+            .update(&i.to_ne_bytes(), &[0; 8], MapFlags::empty())
+            .expect("failed to write");
     }
-    assert_eq!(count, 0);
+    assert_eq!(start.approximate_count().expect("failed to count"), 1500);
 }
 
 #[test]
-fn test_object_map_pin() {
+fn test_set_memlock_rlimit() {
+    // Other tests set the same limit concurrently, keep them from changing it under us
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("runqslower.bpf.o");
-    let map = obj.map_mut("start").expect("failed to find map");
+    let get_rlimit = || {
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlimit) },
+            0
+        );
+        rlimit
+    };
 
-    let path = "/sys/fs/bpf/mymap";
+    // Raising the soft limit up to the hard limit needs no privileges
+    let max = get_rlimit().rlim_max;
+    libbpf_rs::set_memlock_rlimit(max).expect("failed to set rlimit");
+    let rlimit = get_rlimit();
+    assert_eq!(rlimit.rlim_cur, max);
+    assert_eq!(rlimit.rlim_max, max);
+}
 
-    // Unpinning a unpinned map should be an error
-    assert!(map.unpin(path).is_err());
-    assert!(!Path::new(path).exists());
+#[test]
+fn test_probe_program_and_map_type() {
+    bump_rlimit_mlock();
 
-    // Pin and unpin should be successful
-    map.pin(path).expect("failed to pin map");
-    assert!(Path::new(path).exists());
-    map.unpin(path).expect("failed to unpin map");
-    assert!(!Path::new(path).exists());
+    assert!(libbpf_rs::probe_program_type(ProgramType::SocketFilter).expect("failed to probe"));
+    assert!(libbpf_rs::probe_map_type(MapType::Hash).expect("failed to probe"));
+    assert!(libbpf_rs::probe_map_type(MapType::RingBuf).expect("failed to probe"));
+
+    assert!(!libbpf_rs::probe_program_type(ProgramType::Unspec).expect("failed to probe"));
+    assert!(matches!(
+        libbpf_rs::probe_map_type(MapType::Unspec),
+        Err(libbpf_rs::Error::System(libc::EOPNOTSUPP))
+    ));
+    assert!(matches!(
+        libbpf_rs::probe_program_type(ProgramType::Unknown),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        libbpf_rs::probe_map_type(MapType::Unknown),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
 }
 
 #[test]
-fn test_object_programs() {
+fn test_object_map_btf() {
     bump_rlimit_mlock();
 
-    let obj = get_test_object("runqslower.bpf.o");
-    obj.prog("handle__sched_wakeup")
-        .expect("failed to find program");
-    obj.prog("handle__sched_wakeup_new")
-        .expect("failed to find program");
-    obj.prog("handle__sched_switch")
-        .expect("failed to find program");
-    assert!(obj.prog("asdf").is_none());
+    let _obj = get_test_object("runqslower.bpf.o");
+    let info = libbpf_rs::query::MapInfoIter::default()
+        .find(|info| info.name == "start" && info.btf_id != 0)
+        .expect("failed to find map");
+
+    let btf = libbpf_rs::load_btf_by_id(info.btf_id).expect("failed to load btf");
+    assert_eq!(btf.type_name(info.btf_key_type_id), Some("u32"));
+    assert_eq!(btf.type_size(info.btf_key_type_id).unwrap(), 4);
+    assert_eq!(btf.type_name(info.btf_value_type_id), Some("u64"));
+    assert_eq!(btf.type_size(info.btf_value_type_id).unwrap(), 8);
+    assert!(btf.type_name(u32::MAX).is_none());
+
+    assert!(libbpf_rs::load_btf_by_id(u32::MAX).is_err());
 }
 
 #[test]
-fn test_object_programs_iter_mut() {
+fn test_object_btf() {
     bump_rlimit_mlock();
 
     let obj = get_test_object("runqslower.bpf.o");
-    assert!(obj.progs_iter().count() == 3);
+    let btf = obj.btf().expect("object has no btf");
+
+    let ty = btf.type_by_name("list_head").expect("failed to find type");
+    assert_eq!(ty.name(), Some("list_head"));
+    assert_eq!(ty.kind(), BtfKind::Struct);
+    assert_eq!(ty.vlen(), 2);
+    assert_eq!(btf.type_size(ty.id()).unwrap(), 16);
+    assert!(ty.id() < btf.type_cnt());
+
+    let ty = btf.type_by_name("u32").expect("failed to find type");
+    assert_eq!(ty.kind(), BtfKind::Typedef);
+    assert_eq!(btf.type_size(ty.id()).unwrap(), 4);
+
+    let void = btf.type_by_id(0).expect("failed to find void");
+    assert_eq!(void.kind(), BtfKind::Unknown);
+    assert!(void.name().is_none());
+
+    assert!(btf.type_by_name("no_such_type").is_none());
+    assert!(btf.type_by_id(btf.type_cnt()).is_none());
+
+    let file_btf =
+        Btf::from_path(get_test_object_path("runqslower.bpf.o")).expect("failed to parse btf");
+    let file_ty = file_btf
+        .type_by_name("list_head")
+        .expect("failed to find type");
+    assert_eq!(file_ty.kind(), BtfKind::Struct);
+
+    assert!(Btf::from_path("/no/such/file").is_err());
 }
 
 #[test]
-fn test_object_program_pin() {
+fn test_map_create_btf() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("runqslower.bpf.o");
-    let prog = obj
-        .prog_mut("handle__sched_wakeup")
-        .expect("failed to find program");
-
-    let path = "/sys/fs/bpf/myprog";
+    // Borrow the BTF of `start`, which has u32 keys and u64 values
+    let _obj = get_test_object("runqslower.bpf.o");
+    let info = libbpf_rs::query::MapInfoIter::default()
+        .find(|info| info.name == "start" && info.btf_id != 0)
+        .expect("failed to find map");
+    let btf_fd = unsafe { libbpf_rs::libbpf_sys::bpf_btf_get_fd_by_id(info.btf_id) };
+    assert!(btf_fd >= 0, "failed to get btf fd");
+    defer! {
+        let _ = nix::unistd::close(btf_fd);
+    }
 
-    // Unpinning a unpinned prog should be an error
-    assert!(prog.unpin(path).is_err());
-    assert!(!Path::new(path).exists());
+    let opts = MapCreateOpts {
+        btf_fd: Some(btf_fd),
+        btf_key_type_id: info.btf_key_type_id,
+        btf_value_type_id: info.btf_value_type_id,
+        ..Default::default()
+    };
+    let map =
+        Map::create(MapType::Hash, "typed", 4, 8, 16, opts.clone()).expect("failed to create map");
+    map.update(&[1, 2, 3, 4], &[1; 8], MapFlags::empty())
+        .expect("failed to write");
+    let created = libbpf_rs::query::MapInfoIter::default()
+        .find(|info| info.name == "typed")
+        .expect("failed to find created map");
+    assert_eq!(created.btf_id, info.btf_id);
+    assert_eq!(created.btf_key_type_id, info.btf_key_type_id);
+
+    // The u32 key type does not fit 8 byte keys
+    let err = Map::create(MapType::Hash, "typed", 8, 8, 16, opts)
+        .err()
+        .expect("created map with mismatched key type");
+    assert!(matches!(err, libbpf_rs::Error::InvalidInput(_)));
+
+    Map::create(MapType::Hash, "untyped", 8, 8, 16, MapCreateOpts::default())
+        .expect("failed to create map");
+}
 
-    // Pin should be successful
-    prog.pin(path).expect("failed to pin prog");
-    assert!(Path::new(path).exists());
+#[test]
+fn test_map_lookup_update_locked() {
+    bump_rlimit_mlock();
 
-    // Backup cleanup method in case test errors
+    // struct val { struct bpf_spin_lock lock; __u32 data; };
+    let btf = unsafe { libbpf_rs::libbpf_sys::btf__new_empty() };
+    assert!(!btf.is_null());
     defer! {
-        let _ = fs::remove_file(path);
+        unsafe { libbpf_rs::libbpf_sys::btf__free(btf) };
     }
+    let name = |s: &[u8]| s.as_ptr() as *const std::os::raw::c_char;
+    let (u32_id, val_id) = unsafe {
+        let u32_id = libbpf_rs::libbpf_sys::btf__add_int(btf, name(b"__u32\0"), 4, 0);
+        let lock_id = libbpf_rs::libbpf_sys::btf__add_struct(btf, name(b"bpf_spin_lock\0"), 4);
+        assert_eq!(
+            libbpf_rs::libbpf_sys::btf__add_field(btf, name(b"val\0"), u32_id, 0, 0),
+            0
+        );
+        let val_id = libbpf_rs::libbpf_sys::btf__add_struct(btf, name(b"val\0"), 8);
+        assert_eq!(
+            libbpf_rs::libbpf_sys::btf__add_field(btf, name(b"lock\0"), lock_id, 0, 0),
+            0
+        );
+        assert_eq!(
+            libbpf_rs::libbpf_sys::btf__add_field(btf, name(b"data\0"), u32_id, 32, 0),
+            0
+        );
+        assert_eq!(libbpf_rs::libbpf_sys::btf__load_into_kernel(btf), 0);
+        (u32_id as u32, val_id as u32)
+    };
 
-    // Unpin should be successful
-    prog.unpin(path).expect("failed to unpin prog");
-    assert!(!Path::new(path).exists());
+    let opts = MapCreateOpts {
+        btf_fd: Some(unsafe { libbpf_rs::libbpf_sys::btf__fd(btf) }),
+        btf_key_type_id: u32_id,
+        btf_value_type_id: val_id,
+        ..Default::default()
+    };
+    let map = Map::create(MapType::Array, "locked", 4, 8, 1, opts).expect("failed to create map");
+    let key = 0u32.to_ne_bytes();
+    // The lock field is ignored on update and zeroed on lookup
+    let value = [0xff, 0xff, 0xff, 0xff, 1, 2, 3, 4];
+    map.update_locked(&key, &value).expect("failed to update");
+    assert_eq!(
+        map.lookup_locked(&key).expect("failed to lookup"),
+        Some(vec![0, 0, 0, 0, 1, 2, 3, 4])
+    );
+
+    // Values without a spin lock can't be locked
+    let plain = Map::create(MapType::Array, "plain", 4, 8, 1, MapCreateOpts::default())
+        .expect("failed to create map");
+    assert!(matches!(
+        plain.lookup_locked(&key),
+        Err(libbpf_rs::Error::System(libc::EINVAL))
+    ));
 }
 
 #[test]
-fn test_object_link_pin() {
+fn test_object_map_key_iter_empty() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+
+    let mut count = 0;
+    for _ in start.keys() {
+        count += 1;
+    }
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_object_map_pin() {
     bump_rlimit_mlock();
 
     let mut obj = get_test_object("runqslower.bpf.o");
-    let prog = obj
-        .prog_mut("handle__sched_wakeup")
-        .expect("failed to find program");
-    let mut link = prog.attach().expect("failed to attach prog");
+    let map = obj.map_mut("start").expect("failed to find map");
 
-    let path = "/sys/fs/bpf/mylink";
+    let path = "/sys/fs/bpf/mymap";
 
-    // Unpinning a unpinned prog should be an error
-    assert!(link.unpin().is_err());
+    // Unpinning a unpinned map should be an error
+    assert!(map.unpin(path).is_err());
     assert!(!Path::new(path).exists());
+    assert!(!map.is_pinned());
+    assert!(map.pin_path().is_none());
 
-    // Pin should be successful
-    link.pin(path).expect("failed to pin prog");
+    // Pin and unpin should be successful
+    map.pin(path).expect("failed to pin map");
     assert!(Path::new(path).exists());
+    assert!(map.is_pinned());
+    assert_eq!(map.pin_path(), Some(Path::new(path)));
+    map.unpin(path).expect("failed to unpin map");
+    assert!(!Path::new(path).exists());
+    assert!(!map.is_pinned());
+}
 
-    // Backup cleanup method in case test errors
+#[test]
+fn test_object_map_set_pin_path() {
+    bump_rlimit_mlock();
+
+    let path = "/sys/fs/bpf/mymap_autopin";
     defer! {
         let _ = fs::remove_file(path);
     }
 
-    // Unpin should be successful
-    link.unpin().expect("failed to unpin prog");
-    assert!(!Path::new(path).exists());
+    let load = || {
+        let mut open_obj = ObjectBuilder::default()
+            .open_file(get_test_object_path("runqslower.bpf.o"))
+            .expect("failed to open object");
+        let open_map = open_obj.map_mut("start").expect("failed to find map");
+        assert!(open_map.pin_path().is_none());
+        open_map.set_pin_path(path).expect("failed to set pin path");
+        assert_eq!(open_map.pin_path().as_deref(), Some(Path::new(path)));
+        let obj = open_obj.load().expect("failed to load object");
+        assert!(obj.map("start").expect("failed to find map").is_pinned());
+        obj
+    };
+
+    let obj = load();
+    assert!(Path::new(path).exists());
+    let start = obj.map("start").expect("failed to find map");
+    let key = 1u32.to_ne_bytes();
+    start
+        .update(&key, &[3; 8], MapFlags::empty())
+        .expect("failed to write");
+
+    // A second load reuses the pinned map
+    let obj2 = load();
+    assert_eq!(
+        obj2.map("start")
+            .expect("failed to find map")
+            .lookup(&key, MapFlags::empty())
+            .expect("failed to read"),
+        Some(vec![3; 8])
+    );
 }
 
 #[test]
-fn test_object_reuse_pined_map() {
+fn test_object_map_pin_replace() {
     bump_rlimit_mlock();
 
-    let path = "/sys/fs/bpf/mymap";
-    let key = vec![1, 2, 3, 4];
-    let val = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let path = "/sys/fs/bpf/mymap_replace";
 
-    // Pin a map
-    {
-        let mut obj = get_test_object("runqslower.bpf.o");
-        let map = obj.map_mut("start").expect("failed to find map");
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
 
-        map.update(&key, &val, MapFlags::empty())
-            .expect("failed to write");
+    let mut old_obj = get_test_object("runqslower.bpf.o");
+    let old_map = old_obj.map_mut("start").expect("failed to find map");
+    old_map.pin(path).expect("failed to pin map");
+    let old_pinned = PinnedMap::try_from_path(path).expect("failed to open pinned map");
+
+    let mut new_obj = get_test_object("runqslower.bpf.o");
+    let new_map = new_obj.map_mut("start").expect("failed to find map");
+    new_map
+        .update(&[1, 2, 3, 4], &[1; 8], MapFlags::empty())
+        .expect("failed to write");
+    new_map.pin_replace(path).expect("failed to replace pin");
+
+    let new_pinned = PinnedMap::try_from_path(path).expect("failed to open pinned map");
+    assert_eq!(
+        new_pinned
+            .lookup(&[1, 2, 3, 4], MapFlags::empty())
+            .expect("failed to read map"),
+        Some(vec![1; 8])
+    );
+    // The old map is still in use by whoever holds it
+    assert!(old_pinned
+        .lookup(&[1, 2, 3, 4], MapFlags::empty())
+        .expect("failed to read map")
+        .is_none());
+
+    // No temporary pin is left behind
+    assert_eq!(
+        fs::read_dir("/sys/fs/bpf")
+            .unwrap()
+            .filter(|e| e
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("mymap_replace_tmp"))
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_object_pin_with_mode() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let map_path = "/sys/fs/bpf/mymap_mode";
+    let prog_path = "/sys/fs/bpf/myprog_mode";
+
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(map_path);
+        let _ = fs::remove_file(prog_path);
+    }
+
+    let map = obj.map_mut("start").expect("failed to find map");
+    map.pin_with_mode(map_path, 0o600)
+        .expect("failed to pin map");
+    let mode = fs::metadata(map_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    map.unpin(map_path).expect("failed to unpin map");
+
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    prog.pin_with_mode(prog_path, 0o640)
+        .expect("failed to pin prog");
+    let mode = fs::metadata(prog_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    prog.unpin(prog_path).expect("failed to unpin prog");
+}
+
+#[test]
+fn test_object_programs() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    obj.prog("handle__sched_wakeup")
+        .expect("failed to find program");
+    obj.prog("handle__sched_wakeup_new")
+        .expect("failed to find program");
+    obj.prog("handle__sched_switch")
+        .expect("failed to find program");
+    assert!(obj.prog("asdf").is_none());
+}
+
+struct RunqslowerBinding<'a> {
+    start: &'a mut Map,
+    events: &'a mut Map,
+    handle_wakeup: &'a mut Program,
+}
+
+impl<'a> ObjectBinding<'a> for RunqslowerBinding<'a> {
+    const MAPS: &'static [&'static str] = &["start", "events"];
+    const PROGS: &'static [&'static str] = &["handle__sched_wakeup"];
+
+    fn from_parts(maps: Vec<&'a mut Map>, progs: Vec<&'a mut Program>) -> Self {
+        let mut maps = maps.into_iter();
+        let mut progs = progs.into_iter();
+        RunqslowerBinding {
+            start: maps.next().unwrap(),
+            events: maps.next().unwrap(),
+            handle_wakeup: progs.next().unwrap(),
+        }
+    }
+}
+
+struct MissingBinding;
+
+impl<'a> ObjectBinding<'a> for MissingBinding {
+    const MAPS: &'static [&'static str] = &["start", "asdf"];
+    const PROGS: &'static [&'static str] = &[];
+
+    fn from_parts(_maps: Vec<&'a mut Map>, _progs: Vec<&'a mut Program>) -> Self {
+        MissingBinding
+    }
+}
+
+struct DuplicateBinding;
+
+impl<'a> ObjectBinding<'a> for DuplicateBinding {
+    const MAPS: &'static [&'static str] = &["start", "start"];
+    const PROGS: &'static [&'static str] = &[];
+
+    fn from_parts(_maps: Vec<&'a mut Map>, _progs: Vec<&'a mut Program>) -> Self {
+        DuplicateBinding
+    }
+}
+
+#[test]
+fn test_object_bind_to() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let skel: RunqslowerBinding = obj.bind_to().expect("failed to bind object");
+    assert_eq!(skel.start.name(), "start");
+    assert_eq!(skel.events.name(), "events");
+    assert_eq!(skel.handle_wakeup.name(), "handle__sched_wakeup");
+
+    assert!(obj.bind_to::<MissingBinding>().is_err());
+    match obj.bind_to::<DuplicateBinding>() {
+        Err(libbpf_rs::Error::InvalidInput(msg)) => assert_eq!(msg, "duplicate map name start"),
+        _ => panic!("expected a duplicate map name error"),
+    }
+}
+
+#[test]
+fn test_object_program_verifier_log() {
+    bump_rlimit_mlock();
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to open object");
+    open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program")
+        .set_log_level(2)
+        .expect("failed to set log level");
+    let obj = open_obj.load().expect("failed to load object");
+
+    let log = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program")
+        .verifier_log()
+        .expect("no verifier log");
+    assert!(!log.is_empty());
+    assert!(obj
+        .prog("handle__sched_wakeup_new")
+        .expect("failed to find program")
+        .verifier_log()
+        .is_none());
+}
+
+#[test]
+fn test_object_program_log_buf_size() {
+    bump_rlimit_mlock();
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    let prog = open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    assert!(matches!(
+        prog.set_log_buf_size(1),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    prog.set_log_buf_size(1 << 20)
+        .expect("failed to set log buffer size");
+    prog.set_log_level(1).expect("failed to set log level");
+    let obj = open_obj.load().expect("failed to load object");
+    assert!(!obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program")
+        .verifier_log()
+        .expect("no verifier log")
+        .is_empty());
+
+    // The load fails if the log doesn't fit
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    let prog = open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    prog.set_log_buf_size(128)
+        .expect("failed to set log buffer size");
+    prog.set_log_level(2).expect("failed to set log level");
+    assert!(open_obj.load().is_err());
+}
+
+#[test]
+fn test_object_program_set_attach_target() {
+    bump_rlimit_mlock();
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    let prog = open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    assert!(matches!(
+        prog.set_attach_target(0, Some("bad\0name")),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    prog.set_attach_target(0, Some("sched_wakeup_new"))
+        .expect("failed to set attach target");
+    let mut obj = open_obj.load().expect("failed to load object");
+    let link = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program")
+        .attach()
+        .expect("failed to attach program");
+    drop(link);
+
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    // Kernel targets are resolved right away
+    let res = open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program")
+        .set_attach_target(0, Some("libbpf_rs_no_such_tracepoint"));
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+}
+
+#[test]
+fn test_object_program_verifier_log_load_failure() {
+    bump_rlimit_mlock();
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    open_obj
+        .prog_mut("handle__sched_switch")
+        .expect("failed to find program")
+        .set_autoload(false)
+        .expect("failed to set autoload");
+    // Loaded before the failing program, its log is left out of the error
+    open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program")
+        .set_log_level(1)
+        .expect("failed to set log level");
+    // A tp_btf program does not pass the verifier as a socket filter
+    open_obj
+        .prog_mut("handle__sched_wakeup_new")
+        .expect("failed to find program")
+        .set_prog_type(ProgramType::SocketFilter);
+
+    match open_obj.load() {
+        Err(libbpf_rs::Error::Verifier { errno, log }) => {
+            assert!(errno > 0);
+            assert!(log.starts_with("handle__sched_wakeup_new:\n"));
+            assert!(log.len() > "handle__sched_wakeup_new:\n".len());
+            assert!(!log.contains("handle__sched_wakeup:\n"));
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("load unexpectedly succeeded"),
+    }
+
+    // Failures outside the verifier keep the errno
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(&obj_path)
+        .expect("failed to open object");
+    open_obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program")
+        .set_log_level(1)
+        .expect("failed to set log level");
+    open_obj
+        .map_mut("start")
+        .expect("failed to find map")
+        .set_max_entries(0)
+        .expect("failed to set max entries");
+    assert!(matches!(
+        open_obj.load(),
+        Err(libbpf_rs::Error::System(libc::EINVAL))
+    ));
+}
+
+#[test]
+fn test_program_attach_type_kernel_name() {
+    for i in 0..=ProgramAttachType::LsmMac as u32 {
+        let ty = ProgramAttachType::try_from(i).expect("invalid attach type");
+        let name = unsafe { CStr::from_ptr(libbpf_rs::libbpf_sys::libbpf_bpf_attach_type_str(i)) };
+        assert_eq!(ty.kernel_name(), name.to_str().unwrap());
+        assert_eq!(
+            ProgramAttachType::from_kernel_name(ty.kernel_name()).map(|ty| ty as u32),
+            Some(i)
+        );
+    }
+
+    assert!(ProgramAttachType::from_kernel_name("asdf").is_none());
+}
+
+#[test]
+fn test_program_type_kernel_name() {
+    for i in 0..=ProgramType::Lsm as u32 {
+        let ty = ProgramType::try_from(i).expect("invalid program type");
+        let name = unsafe { CStr::from_ptr(libbpf_rs::libbpf_sys::libbpf_bpf_prog_type_str(i)) };
+        assert_eq!(ty.kernel_name(), name.to_str().unwrap());
+        assert_eq!(
+            ProgramType::from_kernel_name(ty.kernel_name()).map(|ty| ty as u32),
+            Some(i)
+        );
+    }
+
+    assert!(ProgramType::from_kernel_name("asdf").is_none());
+}
+
+#[test]
+fn test_map_type_classifiers() {
+    let types: Vec<_> = (0..=MapType::BloomFilter as u32)
+        .map(|i| MapType::try_from(i).expect("invalid map type"))
+        .collect();
+    let filter =
+        |f: fn(&MapType) -> bool| types.iter().filter(|ty| f(ty)).cloned().collect::<Vec<_>>();
+
+    assert_eq!(
+        filter(MapType::is_per_cpu),
+        [
+            MapType::PercpuHash,
+            MapType::PercpuArray,
+            MapType::LruPercpuHash,
+            MapType::PercpuCgroupStorage
+        ]
+    );
+    assert_eq!(
+        filter(MapType::is_keyless),
+        [MapType::Queue, MapType::Stack, MapType::BloomFilter]
+    );
+    assert_eq!(
+        filter(MapType::is_map_of_maps),
+        [MapType::ArrayOfMaps, MapType::HashOfMaps]
+    );
+    assert!(!MapType::Unknown.is_per_cpu());
+    assert!(!MapType::Unknown.is_keyless());
+    assert!(!MapType::Unknown.is_map_of_maps());
+}
+
+#[test]
+fn test_map_type_kernel_name() {
+    for i in 0..=MapType::BloomFilter as u32 {
+        let ty = MapType::try_from(i).expect("invalid map type");
+        let name = unsafe { CStr::from_ptr(libbpf_rs::libbpf_sys::libbpf_bpf_map_type_str(i)) };
+        assert_eq!(ty.kernel_name(), name.to_str().unwrap());
+        assert_eq!(MapType::from_kernel_name(ty.kernel_name()), Some(ty));
+    }
+
+    assert_eq!(
+        MapType::from_kernel_name("percpu_hash"),
+        Some(MapType::PercpuHash)
+    );
+    assert!(MapType::from_kernel_name("asdf").is_none());
+}
+
+#[test]
+fn test_object_program_attach_socket() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let sock = std::net::UdpSocket::bind("127.0.0.1:0").expect("failed to create socket");
+    // Only socket filters can be attached to sockets
+    assert!(prog.attach_socket(sock.as_raw_fd()).is_err());
+}
+
+#[test]
+fn test_object_program_dump_insns() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    assert!(!prog.dump_insns().expect("failed to dump insns").is_empty());
+}
+
+#[test]
+fn test_object_memlock_bytes() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+    assert!(prog.memlock_bytes().expect("failed to read memlock") > 0);
+
+    let start = obj.map("start").expect("failed to find map");
+    assert!(start.memlock_bytes().expect("failed to read memlock") > 0);
+}
+
+#[test]
+fn test_object_program_attach_uprobe_sleepable() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    assert!(!prog.is_sleepable());
+
+    let err = prog
+        .attach_uprobe_sleepable(false, -1, "/proc/self/exe", 0)
+        .err()
+        .expect("attached non-sleepable program");
+    assert!(matches!(err, libbpf_rs::Error::InvalidInput(_)));
+}
+
+#[test]
+fn test_object_program_set_flags() {
+    bump_rlimit_mlock();
+
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("taskiter.bpf.o"))
+        .expect("failed to open object");
+    let prog = open_obj
+        .prog_mut("dump_pid")
+        .expect("failed to find program");
+    assert_eq!(prog.flags() & libbpf_sys::BPF_F_SLEEPABLE, 0);
+    prog.set_flags(prog.flags() | libbpf_sys::BPF_F_SLEEPABLE)
+        .expect("failed to set flags");
+    assert_ne!(prog.flags() & libbpf_sys::BPF_F_SLEEPABLE, 0);
+
+    let obj = open_obj.load().expect("failed to load object");
+    assert!(obj
+        .prog("dump_pid")
+        .expect("failed to find program")
+        .is_sleepable());
+}
+
+#[test]
+fn test_object_program_attach_uprobe_opts() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let opts = UprobeOpts {
+        func_name: Some("libbpf_rs_no_such_symbol".to_string()),
+        cookie: 42,
+        ..Default::default()
+    };
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_uprobe_opts(false, -1, "/proc/self/exe", 0, opts);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let opts = UprobeOpts {
+        func_name: Some("bad\0name".to_string()),
+        ..Default::default()
+    };
+    let res = prog.attach_uprobe_opts(false, -1, "/proc/self/exe", 0, opts);
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+/// Creates a child cgroup named `name` in the cgroup v2 hierarchy, so tests attaching programs
+/// leave the root cgroup alone.
+fn create_test_cgroup(name: &str) -> PathBuf {
+    let mounts = fs::read_to_string("/proc/mounts").expect("failed to read mounts");
+    let root = mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .map(|fields| fields[1].to_string())
+        .expect("no cgroup v2 hierarchy mounted");
+    let path = Path::new(&root).join(name);
+    let _ = fs::remove_dir(&path);
+    fs::create_dir(&path).expect("failed to create cgroup");
+    path
+}
+
+#[test]
+fn test_program_attach_cgroup_opts() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("cgroup.bpf.o");
+    let prog = obj.prog("allow_ingress").expect("failed to find program");
+    let other = obj
+        .prog("allow_ingress_too")
+        .expect("failed to find program");
+    let path = create_test_cgroup("libbpf-rs-attach-cgroup-opts");
+    defer! {
+        let _ = fs::remove_dir(&path);
+    }
+    let cgroup = fs::File::open(&path).expect("failed to open cgroup");
+    let cgroup_fd = cgroup.as_raw_fd();
+
+    let query = || {
+        let mut attach_flags = 0;
+        let mut prog_ids = [0u32; 4];
+        let mut prog_cnt = prog_ids.len() as u32;
+        let ret = unsafe {
+            libbpf_rs::libbpf_sys::bpf_prog_query(
+                cgroup_fd,
+                ProgramAttachType::CgroupInetIngress as u32,
+                0,
+                &mut attach_flags,
+                prog_ids.as_mut_ptr(),
+                &mut prog_cnt,
+            )
+        };
+        assert_eq!(ret, 0);
+        (attach_flags, prog_cnt)
+    };
+
+    // Programs attached with ALLOW_MULTI share the hook
+    prog.attach_cgroup_legacy(cgroup_fd, CgroupAttachFlags::ALLOW_MULTI)
+        .expect("failed to attach program");
+    other
+        .attach_cgroup_legacy(cgroup_fd, CgroupAttachFlags::ALLOW_MULTI)
+        .expect("failed to attach program");
+    assert_eq!(query(), (CgroupAttachFlags::ALLOW_MULTI.bits(), 2));
+
+    // Programs without a link must agree on the flags
+    assert!(matches!(
+        other.attach_cgroup_legacy(cgroup_fd, CgroupAttachFlags::ALLOW_OVERRIDE),
+        Err(libbpf_rs::Error::System(libc::EPERM))
+    ));
+
+    // Links always share the hook and don't take the legacy flags
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_cgroup_opts(cgroup_fd, CgroupAttachFlags::ALLOW_MULTI);
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+
+    prog.detach_cgroup_legacy(cgroup_fd)
+        .expect("failed to detach program");
+    other
+        .detach_cgroup_legacy(cgroup_fd)
+        .expect("failed to detach program");
+    assert_eq!(query().1, 0);
+
+    let _link = prog
+        .attach_cgroup_opts(cgroup_fd, CgroupAttachFlags::empty())
+        .expect("failed to attach program");
+    assert_eq!(query().1, 1);
+}
+
+#[test]
+fn test_object_program_attach_shared() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    // Attaching only needs a shared reference, so the same program can be attached twice
+    let _link1 = prog.attach().expect("failed to attach prog");
+    let _link2 = prog.attach().expect("failed to attach prog");
+}
+
+#[test]
+fn test_object_program_attach_kprobe_multi() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    // Only kprobe.multi programs can be attached
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_kprobe_multi(false, "vfs_*");
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+    let res = prog.attach_kprobe_multi_syms(true, &["vfs_read", "vfs_write"]);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let res = prog.attach_kprobe_multi(false, "bad\0pattern");
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+    let res = prog.attach_kprobe_multi_syms(false, &["vfs_read", "bad\0sym"]);
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_program_attach_usdt() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    // The test binary has no USDT notes
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_usdt(-1, "/proc/self/exe", "libbpf_rs", "no_such_probe", 42);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let res = prog.attach_usdt(-1, "/proc/self/exe", "bad\0provider", "probe", 0);
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_program_attach_perf_event_opts() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let event = PerfEventBuilder::software(libbpf_rs::libbpf_sys::PERF_COUNT_SW_CPU_CLOCK as u64)
+        .sample_freq(100)
+        .build()
+        .expect("failed to open perf event");
+    assert!(event.fd() >= 0);
+
+    // Only perf_event programs can be attached to perf events
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_perf_event_opts(event.fd(), 42);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let res = PerfEventBuilder::software(libbpf_rs::libbpf_sys::PERF_COUNT_SW_CPU_CLOCK as u64)
+        .sample_period(1000)
+        .pid(-1)
+        .build();
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_program_attach_tracepoint_opts() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog("handle__sys_enter_getpid")
+        .expect("failed to find program");
+
+    // A shared handler tells its attachments apart by cookie
+    let _link1 = prog
+        .attach_tracepoint_opts("syscalls", "sys_enter_getpid", 1)
+        .expect("failed to attach prog");
+    let _link2 = prog
+        .attach_tracepoint_opts("syscalls", "sys_enter_getppid", 2)
+        .expect("failed to attach prog");
+
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_tracepoint_opts("syscalls", "does_not_exist", 3);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+}
+
+#[test]
+fn test_object_program_attach_freplace() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let target_fd = obj
+        .prog("handle__sched_wakeup_new")
+        .expect("failed to find program")
+        .fd();
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    // Only Ext programs can replace functions
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_freplace(target_fd, "handle__sched_wakeup_new");
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let res = prog.attach_freplace(target_fd, "bad\0name");
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_program_attach_netns() {
+    bump_rlimit_mlock();
+
+    let mut insn = libbpf_rs::libbpf_sys::bpf_insn {
+        code: 0xb7,
+        ..Default::default()
+    };
+    insn.set_dst_reg(0);
+    // r0 = BPF_OK; exit
+    let insns = [
+        insn,
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::FlowDissector, &insns, "GPL")
+        .expect("failed to create program");
+    let netns = fs::File::open("/proc/self/ns/net").expect("failed to open netns");
+
+    // libbpf takes the attach type from the object the program was loaded from
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_netns(netns.as_raw_fd());
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_program_attach_kprobe_opts() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let opts = KprobeOpts {
+        offset: 4,
+        cookie: 42,
+        ..Default::default()
+    };
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_kprobe_opts(false, "libbpf_rs_no_such_function", opts);
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+
+    let res = prog.attach_kprobe_opts(false, "bad\0name", KprobeOpts::default());
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_program_and_map_info() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+    let info = prog.info().expect("failed to get program info");
+    // Object names are truncated to BPF_OBJ_NAME_LEN - 1 characters
+    assert_eq!(info.name, "handle__sched_w");
+    assert!(matches!(info.ty, ProgramType::Tracing));
+    assert_ne!(info.id, 0);
+    assert!(info.xlated_prog_len > 0);
+
+    let map = obj.map("start").expect("failed to find map");
+    let info = map.info().expect("failed to get map info");
+    assert_eq!(info.name, "start");
+    assert!(matches!(info.ty, MapType::Hash));
+    assert_ne!(info.id, 0);
+    assert_eq!(info.key_size, map.key_size());
+    assert_eq!(info.value_size, map.value_size());
+}
+
+#[test]
+fn test_object_program_insn_cnt() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+    let cnt = prog.insn_cnt();
+    assert!(cnt > 0);
+    assert_eq!(cnt, prog.dump_insns().expect("failed to dump insns").len());
+
+    let info = prog.info().expect("failed to get program info");
+    assert!(info.verified_insns > 0);
+
+    let other = Program::from_id(info.id).expect("failed to open program");
+    assert_eq!(
+        other.insn_cnt(),
+        info.xlated_prog_len as usize / std::mem::size_of::<libbpf_rs::libbpf_sys::bpf_insn>()
+    );
+}
+
+#[test]
+fn test_object_program_is_jited() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let jit_enabled = fs::read_to_string("/proc/sys/net/core/bpf_jit_enable")
+        .map(|s| s.trim() != "0")
+        .unwrap_or(false);
+    assert_eq!(
+        prog.is_jited().expect("failed to query program"),
+        jit_enabled
+    );
+}
+
+#[test]
+fn test_create_program_from_insns() {
+    bump_rlimit_mlock();
+
+    // r0 = 0; exit
+    let insns = [
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0xb7,
+            ..Default::default()
+        },
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::SocketFilter, &insns, "GPL")
+        .expect("failed to create program");
+    assert!(matches!(prog.prog_type(), ProgramType::SocketFilter));
+
+    let dumped = prog.dump_insns().expect("failed to dump insns");
+    assert_eq!(dumped.len(), insns.len());
+    assert_eq!(dumped[1].code, 0x95);
+
+    let sock = std::net::UdpSocket::bind("127.0.0.1:0").expect("failed to create socket");
+    prog.attach_socket(sock.as_raw_fd())
+        .expect("failed to attach to socket");
+
+    // Programs without an Object cannot be attached through libbpf
+    assert!(prog.attach().is_err());
+}
+
+#[test]
+fn test_program_benchmark_per_cpu() {
+    bump_rlimit_mlock();
+
+    // r0 = 0; exit
+    let insns = [
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0xb7,
+            ..Default::default()
+        },
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::SocketFilter, &insns, "GPL")
+        .expect("failed to create program");
+
+    assert!(prog.benchmark_per_cpu(&[0; 64], 0).is_err());
+
+    let results = prog
+        .benchmark_per_cpu(&[0; 64], 10)
+        .expect("failed to benchmark");
+    assert!(!results.is_empty());
+    for (_, result) in results {
+        assert_eq!(result.iterations, 10);
+        assert_eq!(result.retval, 0);
+        assert!(result.min <= result.p50);
+        assert!(result.p50 <= result.p99);
+        assert!(result.p99 <= result.max);
+    }
+}
+
+#[test]
+fn test_create_program_from_insns_license() {
+    bump_rlimit_mlock();
+
+    // call bpf_trace_printk, which is GPL-only; r0 = 0; exit
+    let insns = [
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x85,
+            imm: libbpf_rs::libbpf_sys::BPF_FUNC_trace_printk as i32,
+            ..Default::default()
+        },
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0xb7,
+            ..Default::default()
+        },
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let err =
+        libbpf_rs::create_program_from_insns(ProgramType::SocketFilter, &insns, "Proprietary")
+            .err()
+            .expect("loaded GPL-only helper under non-GPL license");
+    assert!(matches!(err, libbpf_rs::Error::InvalidInput(_)));
+}
+
+#[test]
+fn test_object_load_license() {
+    bump_rlimit_mlock();
+
+    // The object's license is "Proprietary" and its only program calls bpf_trace_printk
+    let obj_path = get_test_object_path("nongpl.bpf.o");
+    let open_obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to open object");
+    match open_obj.load() {
+        Err(libbpf_rs::Error::InvalidInput(msg)) => assert!(msg.contains("call_gpl_helper")),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("loaded GPL-only helper under non-GPL license"),
+    }
+}
+
+#[test]
+fn test_object_programs_iter_mut() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    assert!(obj.progs_iter().count() == 3);
+}
+
+#[test]
+fn test_object_program_pin() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+
+    let path = "/sys/fs/bpf/myprog";
+
+    // Unpinning a unpinned prog should be an error
+    assert!(prog.unpin(path).is_err());
+    assert!(!Path::new(path).exists());
+    assert!(!prog.is_pinned());
+    assert!(prog.pin_path().is_none());
+
+    // Pin should be successful
+    prog.pin(path).expect("failed to pin prog");
+    assert!(Path::new(path).exists());
+    assert!(prog.is_pinned());
+    assert_eq!(prog.pin_path(), Some(Path::new(path)));
+
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    // Unpin should be successful
+    prog.unpin(path).expect("failed to unpin prog");
+    assert!(!Path::new(path).exists());
+    assert!(!prog.is_pinned());
+    assert!(prog.pin_path().is_none());
+}
+
+#[test]
+fn test_object_link_pin() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let mut link = prog.attach().expect("failed to attach prog");
+
+    let path = "/sys/fs/bpf/mylink";
+
+    // Unpinning a unpinned prog should be an error
+    assert!(link.unpin().is_err());
+    assert!(!Path::new(path).exists());
+
+    // Pin should be successful
+    link.pin(path).expect("failed to pin prog");
+    assert!(Path::new(path).exists());
+
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    // Unpin should be successful
+    link.unpin().expect("failed to unpin prog");
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn test_unpin_link() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let mut link = prog.attach().expect("failed to attach prog");
+
+    let path = "/sys/fs/bpf/myunpinlink";
+    let map_path = "/sys/fs/bpf/myunpinmap";
+
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(map_path);
+    }
+
+    link.pin(path).expect("failed to pin link");
+    libbpf_rs::unpin_link(path).expect("failed to unpin link");
+    assert!(!Path::new(path).exists());
+    assert!(libbpf_rs::unpin_link(path).is_err());
+
+    // Pinned maps are left alone
+    obj.map_mut("start")
+        .expect("failed to find map")
+        .pin(map_path)
+        .expect("failed to pin map");
+    assert!(libbpf_rs::unpin_link(map_path).is_err());
+    assert!(Path::new(map_path).exists());
+}
+
+#[test]
+fn test_object_iter_pinned_links() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let dir = "/sys/fs/bpf/test_pinned_links";
+    let link_path = Path::new(dir).join("mylink");
+    let map_path = Path::new(dir).join("mymap");
+
+    fs::create_dir(dir).expect("failed to create pin dir");
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(&link_path);
+        let _ = fs::remove_file(&map_path);
+        let _ = fs::remove_dir(dir);
+    }
+
+    // Pinned maps living next to the link should be skipped
+    obj.map_mut("start")
+        .expect("failed to find map")
+        .pin(&map_path)
+        .expect("failed to pin map");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let mut link = prog.attach().expect("failed to attach prog");
+    link.pin(&link_path).expect("failed to pin link");
+
+    let links = libbpf_rs::iter_pinned_links(dir)
+        .collect::<libbpf_rs::Result<Vec<_>>>()
+        .expect("failed to iterate pinned links");
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].0, link_path);
+
+    assert!(Link::open_pinned(&map_path).is_err());
+    assert!(libbpf_rs::iter_pinned_links("/sys/fs/bpf/asdf")
+        .next()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn test_object_reuse_pined_map() {
+    bump_rlimit_mlock();
+
+    let path = "/sys/fs/bpf/mymap";
+    let key = vec![1, 2, 3, 4];
+    let val = vec![1, 2, 3, 4, 5, 6, 7, 8];
+
+    // Pin a map
+    {
+        let mut obj = get_test_object("runqslower.bpf.o");
+        let map = obj.map_mut("start").expect("failed to find map");
+
+        map.update(&key, &val, MapFlags::empty())
+            .expect("failed to write");
 
         // Pin map
         map.pin(path).expect("failed to pin map");
         assert!(Path::new(path).exists());
     }
 
-    // Backup cleanup method in case test errors somewhere
-    defer! {
-        let _ = fs::remove_file(path);
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    // Reuse the pinned map
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut builder = ObjectBuilder::default();
+    builder.debug(true);
+    let mut open_obj = builder.open_file(obj_path).expect("failed to open object");
+
+    let start = open_obj.map_mut("start").expect("failed to find map");
+    assert!(start.reuse_pinned_map("/asdf").is_err());
+    start.reuse_pinned_map(path).expect("failed to reuse map");
+
+    let mut obj = open_obj.load().expect("Failed to load object");
+    let reused_map = obj.map_mut("start").expect("failed to find map");
+
+    let found_val = reused_map
+        .lookup(&key, MapFlags::empty())
+        .expect("failed to read map")
+        .expect("failed to find key");
+    assert_eq!(&found_val, &val);
+
+    // Cleanup
+    reused_map.unpin(path).expect("failed to unpin map");
+    assert!(!Path::new(path).exists());
+}
+
+#[test]
+fn test_object_map_zero_all() {
+    bump_rlimit_mlock();
+
+    let path = "/sys/fs/bpf/myarray";
+
+    // Pin an array map with the same name and key/value sizes as `start`
+    let name = CString::new("start").unwrap();
+    let fd = unsafe {
+        libbpf_rs::libbpf_sys::bpf_map_create(
+            libbpf_rs::libbpf_sys::BPF_MAP_TYPE_ARRAY,
+            name.as_ptr(),
+            4,
+            8,
+            16,
+            ptr::null(),
+        )
+    };
+    assert!(fd >= 0, "failed to create map");
+    let path_c = CString::new(path).unwrap();
+    let ret = unsafe { libbpf_rs::libbpf_sys::bpf_obj_pin(fd, path_c.as_ptr()) };
+    let _ = nix::unistd::close(fd);
+    assert_eq!(ret, 0, "failed to pin map");
+
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    // Load nothing but the maps, with `start` replaced by the array
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to open object");
+    for prog in open_obj.progs_iter_mut() {
+        prog.set_autoload(false).expect("failed to set autoload");
+    }
+    open_obj
+        .map_mut("start")
+        .expect("failed to find map")
+        .reuse_pinned_map(path)
+        .expect("failed to reuse map");
+    let obj = open_obj.load().expect("failed to load object");
+
+    let array = obj.map("start").expect("failed to find map");
+    assert_eq!(array.map_type(), MapType::Array);
+    for i in 0..16u32 {
+        array
+            .update(&i.to_ne_bytes(), &[0xff; 8], MapFlags::empty())
+            .expect("failed to write");
+    }
+    array.zero_all().expect("failed to zero map");
+    for i in 0..16u32 {
+        let val = array
+            .lookup(&i.to_ne_bytes(), MapFlags::empty())
+            .expect("failed to read map")
+            .expect("failed to find key");
+        assert_eq!(val, vec![0; 8]);
+    }
+
+    let events = obj.map("events").expect("failed to find map");
+    assert!(events.zero_all().is_err());
+}
+
+#[test]
+fn test_cgroup_storage_key() {
+    bump_rlimit_mlock();
+
+    let path = "/sys/fs/bpf/mycgroupstorage";
+
+    let name = CString::new("mystorage").unwrap();
+    let fd = unsafe {
+        libbpf_rs::libbpf_sys::bpf_map_create(
+            libbpf_rs::libbpf_sys::BPF_MAP_TYPE_CGROUP_STORAGE,
+            name.as_ptr(),
+            16,
+            8,
+            0,
+            ptr::null(),
+        )
+    };
+    assert!(fd >= 0, "failed to create map");
+    let path_c = CString::new(path).unwrap();
+    let ret = unsafe { libbpf_rs::libbpf_sys::bpf_obj_pin(fd, path_c.as_ptr()) };
+    let _ = nix::unistd::close(fd);
+    assert_eq!(ret, 0, "failed to pin map");
+
+    // Backup cleanup method in case test errors somewhere
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    let map = PinnedMap::try_from_path(path).expect("failed to open pinned map");
+    let key = CgroupStorageKey::new(42, ProgramAttachType::CgroupInetEgress);
+    let bytes = key.to_bytes(&map).expect("failed to build key");
+    assert_eq!(bytes.len(), 16);
+    assert_eq!(bytes[..8], 42u64.to_ne_bytes());
+    assert_eq!(
+        bytes[8..12],
+        (ProgramAttachType::CgroupInetEgress as u32).to_ne_bytes()
+    );
+
+    // No program is attached, so there is no storage for the cgroup
+    assert!(map
+        .lookup(&bytes, MapFlags::empty())
+        .map_or(true, |v| v.is_none()));
+
+    let key =
+        CgroupStorageKey::from_cgroup_path("/sys/fs/cgroup", ProgramAttachType::CgroupInetEgress)
+            .expect("failed to build key from path");
+    assert!(key.cgroup_inode_id != 0);
+    assert!(CgroupStorageKey::from_cgroup_path(path, ProgramAttachType::CgroupInetEgress).is_err());
+
+    // Only cgroup storage maps take these keys
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    assert!(key.to_bytes(start).is_err());
+}
+
+#[test]
+fn test_object_program_can_use_helper() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog("handle__sys_enter_getpid")
+        .expect("failed to find program");
+
+    for _ in 0..2 {
+        assert!(prog
+            .can_use_helper(libbpf_rs::libbpf_sys::BPF_FUNC_ringbuf_output)
+            .expect("failed to probe helper"));
+        assert!(!prog
+            .can_use_helper(libbpf_rs::libbpf_sys::BPF_FUNC_sk_redirect_map)
+            .expect("failed to probe helper"));
+    }
+}
+
+#[test]
+fn test_object_ringbuf() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sys_enter_getpid")
+        .expect("failed to find program");
+    let _link = prog.attach().expect("failed to attach prog");
+
+    static mut V1: i32 = 0;
+    static mut V2: i32 = 0;
+
+    fn callback1(data: &[u8]) -> i32 {
+        let mut value: i32 = 0;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+
+        unsafe {
+            V1 = value;
+        }
+
+        0
+    }
+
+    fn callback2(data: &[u8]) -> i32 {
+        let mut value: i32 = 0;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+
+        unsafe {
+            V2 = value;
+        }
+
+        0
+    }
+
+    // Test trying to build without adding any ringbufs
+    // Can't use expect_err here since RingBuffer does not implement Debug
+    let builder = libbpf_rs::RingBufferBuilder::new();
+    assert!(
+        builder.build().is_err(),
+        "Should not be able to build without adding at least one ringbuf"
+    );
+
+    // Test building with multiple map objects
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+
+    // Add a first map and callback
+    let map1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+
+    builder.add(map1, callback1).expect("Failed to add ringbuf");
+
+    // Add a second map and callback
+    let map2 = obj.map("ringbuf2").expect("Failed to get ringbuf2 map");
+
+    builder.add(map2, callback2).expect("Failed to add ringbuf");
+
+    let mgr = builder.build().expect("Failed to build");
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    // This should result in both callbacks being called
+    mgr.consume().expect("Failed to consume ringbuf");
+
+    // Our values should both reflect that the callbacks have been called
+    unsafe { assert_eq!(V1, 1) };
+    unsafe { assert_eq!(V2, 2) };
+
+    // Reset both values
+    unsafe { V1 = 0 };
+    unsafe { V2 = 0 };
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    // This should result in both callbacks being called
+    mgr.poll(Duration::from_millis(100))
+        .expect("Failed to poll ringbuf");
+
+    // Our values should both reflect that the callbacks have been called
+    unsafe { assert_eq!(V1, 1) };
+    unsafe { assert_eq!(V2, 2) };
+}
+
+#[test]
+fn test_object_ringbuf_closure() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sys_enter_getpid")
+        .expect("failed to find program");
+    let _link = prog.attach().expect("failed to attach prog");
+
+    let (sender1, receiver1) = channel();
+    let callback1 = move |data: &[u8]| -> i32 {
+        let mut value: i32 = 0;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+
+        sender1.send(value).expect("Failed to send value");
+
+        0
+    };
+
+    let (sender2, receiver2) = channel();
+    let callback2 = move |data: &[u8]| -> i32 {
+        let mut value: i32 = 0;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+
+        sender2.send(value).expect("Failed to send value");
+
+        0
+    };
+
+    // Test trying to build without adding any ringbufs
+    // Can't use expect_err here since RingBuffer does not implement Debug
+    let builder = libbpf_rs::RingBufferBuilder::new();
+    assert!(
+        builder.build().is_err(),
+        "Should not be able to build without adding at least one ringbuf"
+    );
+
+    // Test building with multiple map objects
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+
+    // Add a first map and callback
+    let map1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+
+    builder.add(map1, callback1).expect("Failed to add ringbuf");
+
+    // Add a second map and callback
+    let map2 = obj.map("ringbuf2").expect("Failed to get ringbuf2 map");
+
+    builder.add(map2, callback2).expect("Failed to add ringbuf");
+
+    let mgr = builder.build().expect("Failed to build");
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    // This should result in both callbacks being called
+    mgr.consume().expect("Failed to consume ringbuf");
+
+    let v1 = receiver1.recv().expect("Failed to receive value");
+    let v2 = receiver2.recv().expect("Failed to receive value");
+
+    assert_eq!(v1, 1);
+    assert_eq!(v2, 2);
+}
+
+#[test]
+fn test_object_ringbuf_available_bytes() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sys_enter_getpid")
+        .expect("failed to find program");
+    let _link = prog.attach().expect("failed to attach prog");
+
+    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    let map1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+    builder.add(map1, |_| 0).expect("Failed to add ringbuf");
+    let map2 = obj.map("ringbuf2").expect("Failed to get ringbuf2 map");
+    builder.add(map2, |_| 0).expect("Failed to add ringbuf");
+    let mgr = builder.build().expect("Failed to build");
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    // Both records plus their headers are pending
+    assert!(mgr.available_bytes() >= 2 * 8);
+    mgr.consume().expect("Failed to consume ringbuf");
+}
+
+#[test]
+fn test_object_ringbuf_spawn_consumer() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sys_enter_getpid")
+        .expect("failed to find program");
+    let _link = prog.attach().expect("failed to attach prog");
+
+    let ringbuf1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+    let (handle, receiver) =
+        libbpf_rs::RingBuffer::spawn_consumer(ringbuf1).expect("Failed to spawn consumer");
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    let data = receiver
+        .recv_timeout(Duration::from_secs(5))
+        .expect("Failed to receive value");
+    let mut value: i32 = 0;
+    plain::copy_from_bytes(&mut value, &data).expect("Wrong size");
+    assert_eq!(value, 1);
+
+    // Hanging up stops the consumer on the next event
+    drop(receiver);
+    unsafe { libc::getpid() };
+    handle
+        .join()
+        .expect("Consumer panicked")
+        .expect("Consumer failed");
+}
+
+#[test]
+fn test_object_attach_iter() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog_mut("dump_pid").expect("Failed to find program");
+    let link = prog.attach_iter(None).expect("Failed to attach prog");
+    let mut iter = Iter::new(&link).expect("Failed to create iterator");
+    let mut buf = Vec::new();
+    let bytes_read = iter
+        .read_to_end(&mut buf)
+        .expect("Failed to read from iterator");
+    assert!(bytes_read > 0);
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("Failed to find program");
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_iter(None);
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_link_fd_and_info() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog("dump_pid").expect("failed to find program");
+    let link = prog.attach_iter(None).expect("failed to attach prog");
+    assert!(link.fd() >= 0);
+    assert_eq!(link.fd(), link.get_fd());
+
+    // The info ties the link back to its program
+    let info = link.info().expect("failed to get link info");
+    assert!(info.id > 0);
+    assert!(matches!(info.info, libbpf_rs::query::LinkTypeInfo::Iter));
+    assert_eq!(info.prog_id, prog.id().expect("failed to get program id"));
+}
+
+#[test]
+fn test_link_update_program() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog("dump_pid").expect("failed to find program");
+    let mut link = prog.attach_iter(None).expect("failed to attach prog");
+
+    // An iterator program for the same target can take over the link
+    let other_obj = get_test_object("taskiter.bpf.o");
+    let other = other_obj.prog("dump_pid").expect("failed to find program");
+    link.update_program(other)
+        .expect("failed to update program");
+    let info = link.info().expect("failed to get link info");
+    assert_eq!(info.prog_id, other.id().expect("failed to get program id"));
+
+    let runq_obj = get_test_object("runqslower.bpf.o");
+    let runq = runq_obj
+        .prog("handle__sched_wakeup")
+        .expect("failed to find program");
+    assert!(matches!(
+        link.update_program(runq),
+        Err(libbpf_rs::Error::System(_))
+    ));
+}
+
+#[test]
+fn test_object_task_iter() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog_mut("dump_pid").expect("Failed to find program");
+    let link = prog.attach().expect("Failed to attach prog");
+    let mut iter = Iter::new(&link).expect("Failed to create iterator");
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct IndexPidPair {
+        i: u32,
+        pid: i32,
+    }
+
+    unsafe impl Plain for IndexPidPair {}
+
+    let mut buf = Vec::new();
+    let bytes_read = iter
+        .read_to_end(&mut buf)
+        .expect("Failed to read from iterator");
+
+    assert!(bytes_read > 0);
+    assert_eq!(bytes_read % std::mem::size_of::<IndexPidPair>(), 0);
+    let items: &[IndexPidPair] =
+        plain::slice_from_bytes(buf.as_slice()).expect("Input slice cannot satisfy length");
+
+    assert!(!items.is_empty());
+    assert_eq!(items[0].i, 0);
+    assert!(items.windows(2).all(|w| w[0].i + 1 == w[1].i));
+    // Check for init
+    assert!(items.iter().any(|&item| item.pid == 1));
+}
+
+#[test]
+fn test_object_task_iter_target() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("taskiter.bpf.o");
+    let prog = obj.prog_mut("dump_pid").expect("Failed to find program");
+    let pid = std::process::id();
+    let link = prog
+        .attach_iter_task(TaskIterTarget::Process(pid))
+        .expect("Failed to attach prog");
+    let mut iter = Iter::new(&link).expect("Failed to create iterator");
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct IndexPidPair {
+        i: u32,
+        pid: i32,
+    }
+
+    unsafe impl Plain for IndexPidPair {}
+
+    let mut buf = Vec::new();
+    iter.read_to_end(&mut buf)
+        .expect("Failed to read from iterator");
+    let items: &[IndexPidPair] =
+        plain::slice_from_bytes(buf.as_slice()).expect("Input slice cannot satisfy length");
+
+    assert!(!items.is_empty());
+    assert!(items.iter().all(|item| item.pid as u32 == pid));
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("Failed to find program");
+    // Can't use expect_err here since Link does not implement Debug
+    let res = prog.attach_iter_task(TaskIterTarget::Thread(pid));
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_task_storage_key() {
+    bump_rlimit_mlock();
+
+    let key = TaskStorageKey::from_pid(std::process::id()).expect("Failed to open pidfd");
+    assert!(key.pid_fd() >= 0);
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let map = obj.map("start").expect("Failed to find map");
+    key.to_bytes(map).expect_err("Built a key for a hash map");
+}
+
+#[test]
+fn test_init() {
+    bump_rlimit_mlock();
+
+    let opts = libbpf_rs::InitOpts {
+        memlock_limit: Some(8 << 20),
+        ..Default::default()
+    };
+    libbpf_rs::init_with(&opts).expect("Failed to init");
+
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlimit) },
+        0
+    );
+    assert!(rlimit.rlim_cur >= 8 << 20);
+
+    // Objects still load after init
+    get_test_object("runqslower.bpf.o");
+}
+
+#[test]
+fn test_object_map_as_slice() {
+    bump_rlimit_mlock();
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Rodata {
+        min_us: u64,
+        targ_pid: i32,
+        targ_tgid: i32,
+    }
+
+    unsafe impl Plain for Rodata {}
+
+    let obj_path = get_test_object_path("runqslower.bpf.o");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(obj_path)
+        .expect("failed to open object");
+    let rodata = Rodata {
+        min_us: 42,
+        targ_pid: 1,
+        targ_tgid: 2,
+    };
+    open_obj
+        .maps_iter_mut()
+        .find(|map| map.name().ends_with(".rodata"))
+        .expect("failed to find .rodata map")
+        .set_initial_value(unsafe { plain::as_bytes(&rodata) })
+        .expect("failed to set initial value");
+    let obj = open_obj.load().expect("failed to load object");
+
+    let map = obj
+        .maps_iter()
+        .find(|map| map.name().ends_with(".rodata"))
+        .expect("failed to find .rodata map");
+    let values = map.as_slice::<Rodata>().expect("failed to mmap map");
+    assert_eq!(values.len(), 1);
+    let value = values.get(0).expect("failed to get value");
+    assert_eq!(value.min_us, 42);
+    assert_eq!(value.targ_pid, 1);
+    assert_eq!(value.targ_tgid, 2);
+    assert!(values.get(1).is_none());
+    let copy = values.to_vec();
+    assert_eq!(copy.len(), 1);
+    assert_eq!(copy[0].min_us, 42);
+
+    assert!(map.as_slice::<u64>().is_err());
+    assert!(obj
+        .map("start")
+        .expect("failed to find map")
+        .as_slice::<u64>()
+        .is_err());
+}
+
+#[test]
+fn test_map_mmap() {
+    bump_rlimit_mlock();
+
+    let opts = MapCreateOpts {
+        map_flags: MapCreateFlags::MMAPABLE,
+        ..Default::default()
+    };
+    let map = Map::create(MapType::Array, "mmap", 4, 4, 4, opts).expect("failed to create map");
+    let mut region = map.mmap().expect("failed to mmap map");
+    // Values are padded to 8 bytes
+    assert_eq!(region.len(), 4 * 8);
+    region
+        .write_volatile(8, 42u32)
+        .expect("failed to write value");
+    assert!(matches!(
+        region.write_volatile(30, 1u32),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        region.read_volatile::<u32>(1),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    drop(region);
+
+    let val = map
+        .lookup(&1u32.to_ne_bytes(), MapFlags::empty())
+        .expect("failed to lookup")
+        .expect("failed to find value");
+    assert_eq!(val, 42u32.to_ne_bytes());
+
+    map.update(&2u32.to_ne_bytes(), &7u32.to_ne_bytes(), MapFlags::empty())
+        .expect("failed to update");
+    let region = map.mmap().expect("failed to mmap map");
+    assert_eq!(
+        region
+            .read_volatile::<u32>(16)
+            .expect("failed to read value"),
+        7
+    );
+
+    let map = Map::create(MapType::Array, "nommap", 4, 4, 4, MapCreateOpts::default())
+        .expect("failed to create map");
+    assert!(matches!(map.mmap(), Err(libbpf_rs::Error::InvalidInput(_))));
+}
+
+#[test]
+fn test_object_event_buffer() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("ringbuf.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sys_enter_getpid")
+        .expect("failed to find program");
+    let _link = prog.attach().expect("failed to attach prog");
+
+    let (sender, receiver) = channel();
+    let map = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+    let events = libbpf_rs::EventBuffer::new(map, move |data: &[u8]| {
+        let mut value: i32 = 0;
+        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+        sender.send(value).expect("Failed to send value");
+    })
+    .expect("Failed to create event buffer");
+    assert!(matches!(events, libbpf_rs::EventBuffer::RingBuf(_)));
+
+    // Call getpid to ensure the BPF program runs
+    unsafe { libc::getpid() };
+
+    events
+        .poll(Duration::from_millis(100))
+        .expect("Failed to poll event buffer");
+    assert_eq!(receiver.try_recv(), Ok(1));
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let map = obj.map("events").expect("Failed to get events map");
+    let events = libbpf_rs::EventBuffer::new(map, |_| ()).expect("Failed to create event buffer");
+    assert!(matches!(events, libbpf_rs::EventBuffer::PerfEvent(_)));
+
+    let map = obj.map("start").expect("Failed to get start map");
+    assert!(libbpf_rs::EventBuffer::new(map, |_| ()).is_err());
+}
+
+#[test]
+fn test_object_link_open_pinned_info() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let mut link = prog.attach().expect("failed to attach prog");
+
+    let path = "/sys/fs/bpf/myinfolink";
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+
+    link.pin(path).expect("failed to pin link");
+    let info = link.info().expect("failed to get link info");
+    assert!(matches!(
+        info.info,
+        libbpf_rs::query::LinkTypeInfo::RawTracepoint(_)
+    ));
+
+    let adopted = Link::open_pinned(path).expect("failed to open pinned link");
+    let adopted_info = adopted.info().expect("failed to get link info");
+    assert_eq!(adopted_info.id, info.id);
+    assert_eq!(adopted_info.prog_id, info.prog_id);
+    match adopted_info.info {
+        libbpf_rs::query::LinkTypeInfo::RawTracepoint(tp) => assert_eq!(tp.name, "sched_wakeup"),
+        _ => panic!("unexpected link type"),
+    }
+}
+
+#[test]
+fn test_program_prog_run_opts() {
+    bump_rlimit_mlock();
+
+    let mut insn = libbpf_rs::libbpf_sys::bpf_insn {
+        code: 0xb7,
+        imm: 3,
+        ..Default::default()
+    };
+    insn.set_dst_reg(0);
+    // r0 = 3; exit
+    let insns = [
+        insn,
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::SchedCls, &insns, "GPL")
+        .expect("failed to create program");
+
+    let data_in = [0u8; 64];
+    let mut data_out = [0u8; 128];
+    let mut ctx_out = [0u8; SkBuffContext::SIZE];
+    let result = prog
+        .prog_run_opts(ProgRunOpts {
+            repeat: 2,
+            data_in: &data_in,
+            data_out: Some(&mut data_out),
+            ctx_out: Some(&mut ctx_out),
+            ..Default::default()
+        })
+        .expect("failed to run program");
+    assert_eq!(result.retval, 3);
+    assert_eq!(result.data_size_out, data_in.len());
+    assert_eq!(result.ctx_size_out, SkBuffContext::SIZE);
+
+    // Only raw tracepoint programs can be run on a given CPU
+    let res = prog.prog_run_opts(ProgRunOpts {
+        data_in: &data_in,
+        cpu: Some(0),
+        ..Default::default()
+    });
+    assert!(matches!(res, Err(libbpf_rs::Error::System(_))));
+}
+
+#[test]
+fn test_tc_hook() {
+    bump_rlimit_mlock();
+
+    let mut insn = libbpf_rs::libbpf_sys::bpf_insn {
+        code: 0xb7,
+        ..Default::default()
+    };
+    insn.set_dst_reg(0);
+    // r0 = TC_ACT_OK; exit
+    let insns = [
+        insn,
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::SchedCls, &insns, "GPL")
+        .expect("failed to create program");
+
+    // Attach to lo, which always has index 1
+    let mut hook = TcHook::new(1, TcAttachPoint::Ingress);
+    hook.priority(1).handle(1);
+    hook.create()
+        .expect("failed to create hook")
+        .attach(&prog)
+        .expect("failed to attach program");
+    assert_ne!(hook.prog_id(), 0);
+
+    hook.detach().expect("failed to detach program");
+    assert!(matches!(
+        hook.detach(),
+        Err(libbpf_rs::Error::System(libc::ENOENT))
+    ));
+    hook.destroy().expect("failed to destroy hook");
+
+    let xdp = libbpf_rs::create_program_from_insns(ProgramType::Xdp, &insns, "GPL")
+        .expect("failed to create program");
+    assert!(matches!(
+        hook.attach(&xdp),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn test_program_prog_run_skb_ctx() {
+    bump_rlimit_mlock();
+
+    let insn = |code: u8, dst: u8, src: u8, off: i16, imm: i32| {
+        let mut insn = libbpf_rs::libbpf_sys::bpf_insn {
+            code,
+            off,
+            imm,
+            ..Default::default()
+        };
+        insn.set_dst_reg(dst);
+        insn.set_src_reg(src);
+        insn
+    };
+    // r0 = skb->mark; r2 = 42; skb->cb[0] = r2; exit
+    let insns = [
+        insn(0x61, 0, 1, 8, 0),
+        insn(0xb7, 2, 0, 0, 42),
+        insn(0x63, 1, 2, 48, 0),
+        insn(0x95, 0, 0, 0, 0),
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::SchedCls, &insns, "GPL")
+        .expect("failed to create program");
+
+    let ctx = SkBuffContext {
+        mark: 7,
+        priority: 3,
+        cb: [1, 2, 3, 4, 5],
+        ..Default::default()
+    };
+    let mut ctx_out = vec![0; SkBuffContext::SIZE];
+    let (retval, _) = prog
+        .prog_run_ctx(1, &[0; 64], None, &ctx.to_bytes(), Some(&mut ctx_out))
+        .expect("failed to run program");
+    assert_eq!(retval, 7);
+
+    let ctx_out = SkBuffContext::from_bytes(&ctx_out).expect("failed to parse context");
+    assert_eq!(ctx_out.mark, 7);
+    assert_eq!(ctx_out.priority, 3);
+    assert_eq!(ctx_out.cb, [42, 2, 3, 4, 5]);
+
+    assert!(SkBuffContext::from_bytes(&[0; 8]).is_err());
+}
+
+#[test]
+fn test_object_map_supports_lookup_and_delete() {
+    bump_rlimit_mlock();
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    let key = 1u32.to_ne_bytes();
+    start
+        .update(&key, &[1; 8], MapFlags::empty())
+        .expect("failed to write");
+
+    let supported = start
+        .supports_lookup_and_delete()
+        .expect("failed to probe hash map");
+    // The probe leaves the map alone
+    assert!(start
+        .lookup(&key, MapFlags::empty())
+        .expect("failed to read")
+        .is_some());
+    if supported {
+        assert_eq!(
+            start
+                .lookup_and_delete(&key)
+                .expect("failed to lookup and delete"),
+            Some(vec![1; 8])
+        );
+        assert!(start
+            .lookup(&key, MapFlags::empty())
+            .expect("failed to read")
+            .is_none());
+    }
+
+    let array = Map::create(
+        MapType::Array,
+        "probe_array",
+        4,
+        8,
+        1,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+    assert!(!array
+        .supports_lookup_and_delete()
+        .expect("failed to probe array map"));
+
+    // Ring buffers can't be created with a single entry, the probe must not try to
+    let obj = get_test_object("ringbuf.bpf.o");
+    let ringbuf = obj.map("ringbuf1").expect("failed to find map");
+    assert!(!ringbuf
+        .supports_lookup_and_delete()
+        .expect("failed to probe ring buffer"));
+}
+
+#[test]
+fn test_object_struct_ops_validation() {
+    bump_rlimit_mlock();
+
+    let mut obj = get_test_object("runqslower.bpf.o");
+    // Can't use expect_err here since Link does not implement Debug
+    let res = obj
+        .map_mut("start")
+        .expect("failed to find map")
+        .attach_struct_ops();
+    assert!(matches!(res, Err(libbpf_rs::Error::InvalidInput(_))));
+
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let mut link = prog.attach().expect("failed to attach prog");
+    let map = obj.map("start").expect("failed to find map");
+    let err = link
+        .update_map(map)
+        .expect_err("updated the map of a tracing link");
+    assert!(matches!(err, libbpf_rs::Error::InvalidInput(_)));
+}
+
+#[test]
+fn test_map_percpu() {
+    bump_rlimit_mlock();
+
+    let map = Map::create(
+        MapType::PercpuArray,
+        "percpu_array",
+        4,
+        4,
+        1,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+    assert!(map.map_type().is_per_cpu());
+
+    let ncpus = libbpf_rs::num_possible_cpus().expect("failed to get number of CPUs");
+    let key = 0u32.to_ne_bytes();
+    let values: Vec<Vec<u8>> = (0..ncpus as u32)
+        .map(|cpu| cpu.to_ne_bytes().to_vec())
+        .collect();
+    map.update_percpu(&key, &values, MapFlags::empty())
+        .expect("failed to update map");
+    let read = map
+        .lookup_percpu(&key, MapFlags::empty())
+        .expect("failed to lookup map")
+        .expect("key not found");
+    assert_eq!(read, values);
+
+    // The single value API would read or write past a value_size buffer
+    assert!(map.lookup(&key, MapFlags::empty()).is_err());
+    assert!(map.update(&key, &[0; 4], MapFlags::empty()).is_err());
+    assert!(map
+        .update_percpu(&key, &values[..ncpus - 1], MapFlags::empty())
+        .is_err());
+
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    assert!(start.lookup_percpu(&key, MapFlags::empty()).is_err());
+}
+
+#[test]
+fn test_map_percpu_lookup_and_delete() {
+    bump_rlimit_mlock();
+
+    let map = Map::create(
+        MapType::PercpuHash,
+        "percpu_hash",
+        4,
+        4,
+        1,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+
+    let ncpus = libbpf_rs::num_possible_cpus().expect("failed to get number of CPUs");
+    let key = 0u32.to_ne_bytes();
+    let values = vec![vec![1u8; 4]; ncpus];
+    map.update_percpu(&key, &values, MapFlags::empty())
+        .expect("failed to update map");
+
+    // The kernel would write a value per CPU into a value_size buffer
+    assert!(matches!(
+        map.lookup_and_delete(&key),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    // Rejected before reaching the kernel, so nothing was deleted
+    assert!(map
+        .lookup_percpu(&key, MapFlags::empty())
+        .expect("failed to lookup map")
+        .is_some());
+
+    if map
+        .supports_lookup_and_delete()
+        .expect("failed to probe map")
+    {
+        assert_eq!(
+            map.lookup_and_delete_percpu(&key)
+                .expect("failed to lookup and delete"),
+            Some(values)
+        );
+        assert!(map
+            .lookup_percpu(&key, MapFlags::empty())
+            .expect("failed to lookup map")
+            .is_none());
+        assert!(map
+            .lookup_and_delete_percpu(&key)
+            .expect("failed to lookup and delete")
+            .is_none());
     }
 
-    // Reuse the pinned map
-    let obj_path = get_test_object_path("runqslower.bpf.o");
-    let mut builder = ObjectBuilder::default();
-    builder.debug(true);
-    let mut open_obj = builder.open_file(obj_path).expect("failed to open object");
+    let hash = Map::create(MapType::Hash, "hash", 4, 4, 1, MapCreateOpts::default())
+        .expect("failed to create map");
+    assert!(matches!(
+        hash.lookup_and_delete_percpu(&key),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+}
 
-    let start = open_obj.map_mut("start").expect("failed to find map");
-    assert!(start.reuse_pinned_map("/asdf").is_err());
-    start.reuse_pinned_map(path).expect("failed to reuse map");
+#[test]
+fn test_num_possible_cpus() {
+    let ncpus = libbpf_rs::num_possible_cpus().expect("failed to get number of CPUs");
+    assert!(ncpus >= 1);
+
+    // The possible mask is a list of ranges, e.g. "0-3,8-11"
+    let possible =
+        fs::read_to_string("/sys/devices/system/cpu/possible").expect("failed to read mask");
+    let max_cpu = possible
+        .trim()
+        .split(&[',', '-'][..])
+        .map(|cpu| cpu.parse::<usize>().expect("invalid cpu"))
+        .max()
+        .expect("empty mask");
+    assert_eq!(ncpus, max_cpu + 1);
+}
 
-    let mut obj = open_obj.load().expect("Failed to load object");
-    let reused_map = obj.map_mut("start").expect("failed to find map");
+#[test]
+fn test_object_perf_buffer() {
+    bump_rlimit_mlock();
 
-    let found_val = reused_map
-        .lookup(&key, MapFlags::empty())
-        .expect("failed to read map")
-        .expect("failed to find key");
-    assert_eq!(&found_val, &val);
+    let obj = get_test_object("runqslower.bpf.o");
+    let events = obj.map("events").expect("failed to find map");
+
+    let mut builder = libbpf_rs::PerfBufferBuilder::new(events)
+        .sample_cb(|_cpu: i32, _data: &[u8]| ())
+        .lost_cb(|_cpu: i32, _count: u64| ());
+    // Page counts must be a power of two
+    builder.pages(3);
+    // Can't use expect_err here since PerfBuffer does not implement Debug
+    assert!(builder.build().is_err());
+
+    let mut builder = libbpf_rs::PerfBufferBuilder::new(events).sample_cb(|_, _| ());
+    builder.pages(8);
+    let perf = builder.build().expect("failed to build perf buffer");
+    perf.poll(Duration::from_millis(10))
+        .expect("failed to poll perf buffer");
 
-    // Cleanup
-    reused_map.unpin(path).expect("failed to unpin map");
-    assert!(!Path::new(path).exists());
+    let start = obj.map("start").expect("failed to find map");
+    assert!(libbpf_rs::PerfBufferBuilder::new(start).build().is_err());
 }
 
 #[test]
-fn test_object_ringbuf() {
+fn test_object_typed_map() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("ringbuf.bpf.o");
-    let prog = obj
-        .prog_mut("handle__sys_enter_getpid")
-        .expect("failed to find program");
-    let _link = prog.attach().expect("failed to attach prog");
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    let map = libbpf_rs::TypedMap::<u32, u64>::new(start).expect("failed to wrap map");
 
-    static mut V1: i32 = 0;
-    static mut V2: i32 = 0;
+    assert_eq!(map.get(&1).expect("failed to read"), None);
+    map.set(&1, &42, MapFlags::empty())
+        .expect("failed to write");
+    assert_eq!(map.get(&1).expect("failed to read"), Some(42));
+    map.remove(&1).expect("failed to delete");
+    assert_eq!(map.get(&1).expect("failed to read"), None);
 
-    fn callback1(data: &[u8]) -> i32 {
-        let mut value: i32 = 0;
-        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+    assert!(libbpf_rs::TypedMap::<u64, u64>::new(start).is_err());
+    assert!(libbpf_rs::TypedMap::<u32, u32>::new(start).is_err());
+}
 
-        unsafe {
-            V1 = value;
-        }
+#[test]
+fn test_map_push_pop() {
+    bump_rlimit_mlock();
 
-        0
+    let queue = Map::create(MapType::Queue, "queue", 0, 4, 2, MapCreateOpts::default())
+        .expect("failed to create queue");
+    for i in 1..=2u32 {
+        queue
+            .push(&i.to_ne_bytes(), MapFlags::ANY)
+            .expect("failed to push");
     }
+    assert!(matches!(
+        queue.push(&3u32.to_ne_bytes(), MapFlags::ANY),
+        Err(libbpf_rs::Error::System(libc::E2BIG))
+    ));
+    // Drops the oldest element
+    queue
+        .push(&3u32.to_ne_bytes(), MapFlags::EXIST)
+        .expect("failed to push");
+    assert_eq!(queue.pop().unwrap(), Some(2u32.to_ne_bytes().to_vec()));
+    assert_eq!(queue.pop().unwrap(), Some(3u32.to_ne_bytes().to_vec()));
+    assert_eq!(queue.pop().unwrap(), None);
+
+    let stack = Map::create(MapType::Stack, "stack", 0, 4, 2, MapCreateOpts::default())
+        .expect("failed to create stack");
+    for i in 1..=2u32 {
+        stack
+            .push(&i.to_ne_bytes(), MapFlags::ANY)
+            .expect("failed to push");
+    }
+    assert_eq!(stack.pop().unwrap(), Some(2u32.to_ne_bytes().to_vec()));
+    assert!(matches!(
+        stack.push(&[0; 8], MapFlags::ANY),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+
+    let hash = Map::create(MapType::Hash, "hash", 4, 4, 2, MapCreateOpts::default())
+        .expect("failed to create hash map");
+    assert!(matches!(
+        hash.push(&0u32.to_ne_bytes(), MapFlags::ANY),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+    assert!(matches!(hash.pop(), Err(libbpf_rs::Error::InvalidInput(_))));
+}
 
-    fn callback2(data: &[u8]) -> i32 {
-        let mut value: i32 = 0;
-        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
-
-        unsafe {
-            V2 = value;
-        }
+#[test]
+fn test_map_bloom_filter() {
+    bump_rlimit_mlock();
 
-        0
+    let bloom = Map::create(
+        MapType::BloomFilter,
+        "bloom",
+        0,
+        4,
+        100,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create bloom filter");
+    assert_eq!(bloom.map_type(), MapType::BloomFilter);
+    for i in 1..=10u32 {
+        bloom.bloom_add(&i.to_ne_bytes()).expect("failed to add");
     }
+    for i in 1..=10u32 {
+        assert!(bloom
+            .bloom_contains(&i.to_ne_bytes())
+            .expect("failed to check"));
+    }
+    assert!(matches!(
+        bloom.bloom_add(&[0; 8]),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+
+    let hash = Map::create(MapType::Hash, "hash", 4, 4, 2, MapCreateOpts::default())
+        .expect("failed to create hash map");
+    assert!(matches!(
+        hash.bloom_contains(&0u32.to_ne_bytes()),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+}
 
-    // Test trying to build without adding any ringbufs
-    // Can't use expect_err here since RingBuffer does not implement Debug
-    let builder = libbpf_rs::RingBufferBuilder::new();
-    assert!(
-        builder.build().is_err(),
-        "Should not be able to build without adding at least one ringbuf"
-    );
+#[test]
+fn test_object_map_set_map_flags() {
+    bump_rlimit_mlock();
 
-    // Test building with multiple map objects
-    let mut builder = libbpf_rs::RingBufferBuilder::new();
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("runqslower.bpf.o"))
+        .expect("failed to open object");
+    let start = open_obj.map_mut("start").expect("failed to find map");
+    let flags = start.map_flags() | MapCreateFlags::NO_PREALLOC.bits();
+    start.set_map_flags(flags).expect("failed to set map flags");
+    assert_eq!(start.map_flags(), flags);
+
+    let obj = open_obj.load().expect("failed to load object");
+    let info = obj
+        .map("start")
+        .expect("failed to find map")
+        .info()
+        .expect("failed to get map info");
+    assert_eq!(info.map_flags, flags);
+}
 
-    // Add a first map and callback
-    let map1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
+#[test]
+fn test_object_map_numa_node() {
+    bump_rlimit_mlock();
 
-    builder.add(map1, callback1).expect("Failed to add ringbuf");
+    let mut open_obj = ObjectBuilder::default()
+        .open_file(get_test_object_path("runqslower.bpf.o"))
+        .expect("failed to open object");
+    let start = open_obj.map_mut("start").expect("failed to find map");
+    assert_eq!(start.numa_node(), 0);
+    start.set_numa_node(0).expect("failed to set numa node");
+
+    let obj = open_obj.load().expect("failed to load object");
+    let info = obj
+        .map("start")
+        .expect("failed to find map")
+        .info()
+        .expect("failed to get map info");
+    assert_ne!(info.map_flags & MapCreateFlags::NUMA_NODE.bits(), 0);
+}
 
-    // Add a second map and callback
-    let map2 = obj.map("ringbuf2").expect("Failed to get ringbuf2 map");
+#[test]
+fn test_map_create_flags() {
+    bump_rlimit_mlock();
 
-    builder.add(map2, callback2).expect("Failed to add ringbuf");
+    let opts = MapCreateOpts {
+        map_flags: MapCreateFlags::NO_PREALLOC,
+        numa_node: Some(0),
+        ..Default::default()
+    };
+    let map = Map::create(MapType::Hash, "flags", 4, 8, 16, opts).expect("failed to create map");
+    let info = map.info().expect("failed to get map info");
+    assert_eq!(
+        info.map_flags,
+        (MapCreateFlags::NO_PREALLOC | MapCreateFlags::NUMA_NODE).bits()
+    );
 
-    let mgr = builder.build().expect("Failed to build");
+    // Maps of maps can't be created without an inner map template
+    assert!(Map::create(
+        MapType::HashOfMaps,
+        "outer",
+        4,
+        4,
+        1,
+        MapCreateOpts::default()
+    )
+    .is_err());
+}
 
-    // Call getpid to ensure the BPF program runs
-    unsafe { libc::getpid() };
+#[test]
+fn test_map_update_inner_map() {
+    bump_rlimit_mlock();
 
-    // This should result in both callbacks being called
-    mgr.consume().expect("Failed to consume ringbuf");
+    let inner = Map::create(MapType::Array, "inner", 4, 8, 1, MapCreateOpts::default())
+        .expect("failed to create inner map");
 
-    // Our values should both reflect that the callbacks have been called
-    unsafe { assert_eq!(V1, 1) };
-    unsafe { assert_eq!(V2, 2) };
+    let opts = MapCreateOpts {
+        inner_map_fd: Some(inner.fd()),
+        ..Default::default()
+    };
+    let outer =
+        Map::create(MapType::ArrayOfMaps, "outer", 4, 4, 2, opts).expect("failed to create map");
+
+    let key = 1u32.to_ne_bytes();
+    outer
+        .update_inner_map(&key, &inner, MapFlags::ANY)
+        .expect("failed to store inner map");
+    let inner_id = inner.info().expect("failed to get inner map info").id;
+    assert_eq!(
+        outer
+            .lookup(&key, MapFlags::empty())
+            .expect("failed to read"),
+        Some(inner_id.to_ne_bytes().to_vec())
+    );
 
-    // Reset both values
-    unsafe { V1 = 0 };
-    unsafe { V2 = 0 };
+    assert!(matches!(
+        inner.update_inner_map(&0u32.to_ne_bytes(), &outer, MapFlags::ANY),
+        Err(libbpf_rs::Error::InvalidInput(_))
+    ));
+}
 
-    // Call getpid to ensure the BPF program runs
-    unsafe { libc::getpid() };
+#[test]
+fn test_object_map_from_id() {
+    bump_rlimit_mlock();
 
-    // This should result in both callbacks being called
-    mgr.poll(Duration::from_millis(100))
-        .expect("Failed to poll ringbuf");
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    let key = 1u32.to_ne_bytes();
+    start
+        .update(&key, &[2; 8], MapFlags::empty())
+        .expect("failed to write");
 
-    // Our values should both reflect that the callbacks have been called
-    unsafe { assert_eq!(V1, 1) };
-    unsafe { assert_eq!(V2, 2) };
+    let mut info = libbpf_rs::libbpf_sys::bpf_map_info::default();
+    let mut len = std::mem::size_of::<libbpf_rs::libbpf_sys::bpf_map_info>() as u32;
+    let ret = unsafe {
+        libbpf_rs::libbpf_sys::bpf_obj_get_info_by_fd(
+            start.fd(),
+            &mut info as *mut _ as *mut std::ffi::c_void,
+            &mut len,
+        )
+    };
+    assert_eq!(ret, 0);
+    let map = Map::from_id(info.id).expect("failed to open map by id");
+    assert_eq!(map.name(), "start");
+    assert_eq!(map.map_type(), MapType::Hash);
+    assert_eq!(map.key_size(), 4);
+    assert_eq!(map.value_size(), 8);
+    assert_eq!(
+        map.lookup(&key, MapFlags::empty()).expect("failed to read"),
+        Some(vec![2; 8])
+    );
+
+    assert!(Map::from_id(u32::MAX).is_err());
 }
 
 #[test]
-fn test_object_ringbuf_closure() {
+fn test_object_program_from_id() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("ringbuf.bpf.o");
+    let obj = get_test_object("runqslower.bpf.o");
     let prog = obj
-        .prog_mut("handle__sys_enter_getpid")
+        .prog("handle__sched_wakeup")
         .expect("failed to find program");
-    let _link = prog.attach().expect("failed to attach prog");
+    let id = prog.id().expect("failed to get id");
 
-    let (sender1, receiver1) = channel();
-    let callback1 = move |data: &[u8]| -> i32 {
-        let mut value: i32 = 0;
-        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+    let opened = Program::from_id(id).expect("failed to open program by id");
+    assert_eq!(opened.id().expect("failed to get id"), id);
+    assert_eq!(opened.name(), "handle__sched_w");
+    assert_eq!(opened.section(), "");
+    assert!(matches!(opened.prog_type(), ProgramType::Tracing));
 
-        sender1.send(value).expect("Failed to send value");
+    // Can't use expect_err here since Program does not implement Debug
+    assert!(Program::from_id(u32::MAX).is_err());
+}
 
-        0
-    };
+#[test]
+fn test_object_map_count_entries() {
+    bump_rlimit_mlock();
 
-    let (sender2, receiver2) = channel();
-    let callback2 = move |data: &[u8]| -> i32 {
-        let mut value: i32 = 0;
-        plain::copy_from_bytes(&mut value, data).expect("Wrong size");
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    assert_eq!(start.count_entries().expect("failed to count"), 0);
+    for i in 1..=3u32 {
+        start
+            .update(&i.to_ne_bytes(), &[0; 8], MapFlags::empty())
+            .expect("failed to write");
+    }
+    assert_eq!(start.count_entries().expect("failed to count"), 3);
 
-        sender2.send(value).expect("Failed to send value");
+    let array = Map::create(MapType::Array, "array", 4, 4, 5, MapCreateOpts::default())
+        .expect("failed to create map");
+    assert_eq!(array.count_entries().expect("failed to count"), 5);
+}
 
-        0
-    };
+#[test]
+fn test_object_map_clear() {
+    bump_rlimit_mlock();
 
-    // Test trying to build without adding any ringbufs
-    // Can't use expect_err here since RingBuffer does not implement Debug
-    let builder = libbpf_rs::RingBufferBuilder::new();
-    assert!(
-        builder.build().is_err(),
-        "Should not be able to build without adding at least one ringbuf"
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
+    for i in 1..=3u32 {
+        start
+            .update(&i.to_ne_bytes(), &[1; 8], MapFlags::empty())
+            .expect("failed to write");
+    }
+    start.clear().expect("failed to clear map");
+    assert_eq!(start.count_entries().expect("failed to count"), 0);
+    // Clearing an empty map is fine
+    start.clear().expect("failed to clear map");
+
+    let array = Map::create(MapType::Array, "array", 4, 4, 2, MapCreateOpts::default())
+        .expect("failed to create map");
+    let key = 1u32.to_ne_bytes();
+    array
+        .update(&key, &[1; 4], MapFlags::empty())
+        .expect("failed to write");
+    array.clear().expect("failed to clear map");
+    assert_eq!(
+        array
+            .lookup(&key, MapFlags::empty())
+            .expect("failed to read"),
+        Some(vec![0; 4])
     );
+}
 
-    // Test building with multiple map objects
-    let mut builder = libbpf_rs::RingBufferBuilder::new();
-
-    // Add a first map and callback
-    let map1 = obj.map("ringbuf1").expect("Failed to get ringbuf1 map");
-
-    builder.add(map1, callback1).expect("Failed to add ringbuf");
+#[test]
+fn test_object_map_iter() {
+    bump_rlimit_mlock();
 
-    // Add a second map and callback
-    let map2 = obj.map("ringbuf2").expect("Failed to get ringbuf2 map");
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
 
-    builder.add(map2, callback2).expect("Failed to add ringbuf");
+    let mut expected = HashSet::new();
+    for i in 1..=3u32 {
+        let key = i.to_ne_bytes().to_vec();
+        let value = (i as u64 * 10).to_ne_bytes().to_vec();
+        start
+            .update(&key, &value, MapFlags::empty())
+            .expect("failed to write");
+        expected.insert((key, value));
+    }
 
-    let mgr = builder.build().expect("Failed to build");
+    // The BPF programs may add entries of their own
+    let entries = start
+        .iter()
+        .collect::<libbpf_rs::Result<HashSet<_>>>()
+        .expect("failed to read map");
+    assert!(entries.is_superset(&expected));
+
+    // Per-CPU values can't be read as a single value, which must not look like an empty map
+    let percpu = Map::create(
+        MapType::PercpuHash,
+        "iter_percpu",
+        4,
+        8,
+        4,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+    let mut entries = percpu.iter();
+    assert!(matches!(
+        entries.next(),
+        Some(Err(libbpf_rs::Error::InvalidInput(_)))
+    ));
+    assert!(entries.next().is_none());
+}
 
-    // Call getpid to ensure the BPF program runs
-    unsafe { libc::getpid() };
+#[test]
+fn test_object_map_delete_batch() {
+    bump_rlimit_mlock();
 
-    // This should result in both callbacks being called
-    mgr.consume().expect("Failed to consume ringbuf");
+    let obj = get_test_object("runqslower.bpf.o");
+    let start = obj.map("start").expect("failed to find map");
 
-    let v1 = receiver1.recv().expect("Failed to receive value");
-    let v2 = receiver2.recv().expect("Failed to receive value");
+    let mut keys = Vec::new();
+    for i in 1..=3u32 {
+        start
+            .update(&i.to_ne_bytes(), &[0; 8], MapFlags::empty())
+            .expect("failed to write");
+        keys.extend_from_slice(&i.to_ne_bytes());
+    }
 
-    assert_eq!(v1, 1);
-    assert_eq!(v2, 2);
+    assert!(start.delete_batch(&keys, 2, 0, 0).is_err());
+    let deleted = start
+        .delete_batch(&keys, 3, 0, 0)
+        .expect("failed to delete batch");
+    assert_eq!(deleted, 3);
+    for i in 1..=3u32 {
+        assert!(start
+            .lookup(&i.to_ne_bytes(), MapFlags::empty())
+            .expect("failed to read")
+            .is_none());
+    }
 }
 
 #[test]
-fn test_object_task_iter() {
+fn test_map_unsupported() {
     bump_rlimit_mlock();
 
-    let mut obj = get_test_object("taskiter.bpf.o");
-    let prog = obj.prog_mut("dump_pid").expect("Failed to find program");
-    let link = prog.attach().expect("Failed to attach prog");
-    let mut iter = Iter::new(&link).expect("Failed to create iterator");
+    let map = Map::create(
+        MapType::Array,
+        "unsupported",
+        4,
+        8,
+        4,
+        MapCreateOpts::default(),
+    )
+    .expect("failed to create map");
+
+    // Array elements can't be deleted, so neither operation exists for them
+    let keys = [0u8; 8];
+    assert!(matches!(
+        map.delete_batch(&keys, 2, 0, 0),
+        Err(libbpf_rs::Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        map.lookup_and_delete(&[0; 4]),
+        Err(libbpf_rs::Error::Unsupported(_))
+    ));
+}
 
-    #[repr(C)]
-    #[derive(Clone, Copy)]
-    struct IndexPidPair {
-        i: u32,
-        pid: i32,
-    }
+#[test]
+fn test_error_display() {
+    let err = libbpf_rs::Error::System(libc::EPERM);
+    assert_eq!(
+        err.to_string(),
+        "System error, errno: 1 (EPERM: Operation not permitted)"
+    );
+    assert_eq!(
+        libbpf_rs::Error::System(524).to_string(),
+        "System error, errno: 524"
+    );
+    assert_eq!(
+        libbpf_rs::Error::InvalidInput("bad key".to_string()).to_string(),
+        "Invalid input: bad key"
+    );
+    assert_eq!(
+        libbpf_rs::Error::Unsupported(libc::EOPNOTSUPP).to_string(),
+        "Unsupported operation, errno: 95 (EOPNOTSUPP: Operation not supported on transport endpoint)"
+    );
 
-    unsafe impl Plain for IndexPidPair {}
+    // Usable as a std error, e.g. for `?` into `Box<dyn Error>`
+    let err: Box<dyn std::error::Error> = Box::new(err);
+    assert!(err.source().is_none());
+}
 
-    let mut buf = Vec::new();
-    let bytes_read = iter
-        .read_to_end(&mut buf)
-        .expect("Failed to read from iterator");
+#[test]
+fn test_link_detach() {
+    bump_rlimit_mlock();
 
-    assert!(bytes_read > 0);
-    assert_eq!(bytes_read % std::mem::size_of::<IndexPidPair>(), 0);
-    let items: &[IndexPidPair] =
-        plain::slice_from_bytes(buf.as_slice()).expect("Input slice cannot satisfy length");
+    // r0 = XDP_PASS; exit
+    let insns = [
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0xb7,
+            imm: 2,
+            ..Default::default()
+        },
+        libbpf_rs::libbpf_sys::bpf_insn {
+            code: 0x95,
+            ..Default::default()
+        },
+    ];
+    let prog = libbpf_rs::create_program_from_insns(ProgramType::Xdp, &insns, "GPL")
+        .expect("failed to create program");
+
+    // Attach to loopback (ifindex 1) and adopt the link through its pin
+    let link_fd = unsafe {
+        libbpf_rs::libbpf_sys::bpf_link_create(
+            prog.fd(),
+            1,
+            libbpf_rs::libbpf_sys::BPF_XDP,
+            ptr::null(),
+        )
+    };
+    assert!(link_fd >= 0, "failed to create link: {}", -link_fd);
+    let path = "/sys/fs/bpf/mydetachlink";
+    // Backup cleanup method in case test errors
+    defer! {
+        let _ = fs::remove_file(path);
+    }
+    let path_c = CString::new(path).unwrap();
+    assert_eq!(
+        unsafe { libbpf_rs::libbpf_sys::bpf_obj_pin(link_fd, path_c.as_ptr()) },
+        0
+    );
+    unsafe { libc::close(link_fd) };
 
-    assert!(!items.is_empty());
-    assert_eq!(items[0].i, 0);
-    assert!(items.windows(2).all(|w| w[0].i + 1 == w[1].i));
-    // Check for init
-    assert!(items.iter().any(|&item| item.pid == 1));
+    let link = Link::open_pinned(path).expect("failed to open pinned link");
+    link.detach().expect("failed to detach link");
+
+    // Raw tracepoint links can't be detached
+    let mut obj = get_test_object("runqslower.bpf.o");
+    let prog = obj
+        .prog_mut("handle__sched_wakeup")
+        .expect("failed to find program");
+    let link = prog.attach().expect("failed to attach prog");
+    assert!(matches!(
+        link.detach(),
+        Err(libbpf_rs::Error::System(libc::EOPNOTSUPP))
+    ));
 }